@@ -0,0 +1,203 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+use clap::ValueEnum;
+use ignore::gitignore::Gitignore;
+
+/// Order in which files claim the budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Priority {
+    /// READMEs and entry points first, tests last, otherwise in output order
+    Default,
+    /// Smallest files first, fitting as many files as possible
+    SmallestFirst,
+    /// Plain output order (see --sort)
+    WalkOrder,
+}
+
+/// A code file competing for a place in the output.
+pub struct Candidate {
+    pub path: PathBuf,
+    pub size: u64,
+    pub tokens: usize,
+}
+
+pub struct Budget {
+    pub max_tokens: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub priority: Priority,
+    /// Files matching an earlier glob are considered before later ones, and
+    /// before files matching none.
+    pub(crate) priority_globs: Vec<Gitignore>,
+}
+
+impl Budget {
+    pub fn is_limited(&self) -> bool {
+        self.max_tokens.is_some() || self.max_bytes.is_some()
+    }
+
+    /// Returns the candidates that do not fit, in their original order.
+    ///
+    /// Candidates are visited in priority order and each is kept if it still
+    /// fits in what is left of the budget, so a large file does not prevent
+    /// smaller, lower-priority files from being included.
+    pub fn omitted<'a>(&self, candidates: &'a [Candidate]) -> Vec<&'a Candidate> {
+        if !self.is_limited() {
+            return Vec::new();
+        }
+
+        let mut order: Vec<&Candidate> = candidates.iter().collect();
+        order.sort_by_key(|c| (self.glob_rank(&c.path), self.strategy_rank(c)));
+
+        let mut tokens_left = self.max_tokens.unwrap_or(usize::MAX);
+        let mut bytes_left = self.max_bytes.unwrap_or(u64::MAX);
+        let mut omitted = HashSet::new();
+
+        for candidate in order {
+            if candidate.tokens <= tokens_left && candidate.size <= bytes_left {
+                tokens_left -= candidate.tokens;
+                bytes_left -= candidate.size;
+            } else {
+                omitted.insert(&candidate.path);
+            }
+        }
+
+        candidates.iter().filter(|c| omitted.contains(&c.path)).collect()
+    }
+
+    fn glob_rank(&self, path: &Path) -> usize {
+        self.priority_globs
+            .iter()
+            .position(|glob| glob.matched_path_or_any_parents(path, false).is_ignore())
+            .unwrap_or(self.priority_globs.len())
+    }
+
+    fn strategy_rank(&self, candidate: &Candidate) -> (u8, u64) {
+        match self.priority {
+            Priority::Default => (default_tier(&candidate.path), 0),
+            Priority::SmallestFirst => {
+                let size = match self.max_tokens {
+                    Some(_) => candidate.tokens as u64,
+                    None => candidate.size,
+                };
+                (0, size)
+            }
+            Priority::WalkOrder => (0, 0),
+        }
+    }
+}
+
+/// 0 for documentation, 1 for entry points and manifests, 3 for tests and 2
+/// for everything else.
+fn default_tier(path: &Path) -> u8 {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let stem = name.split('.').next().unwrap_or_default();
+
+    if is_test(path, &name) {
+        3
+    } else if stem == "readme" {
+        0
+    } else if matches!(
+        stem,
+        "main" | "lib" | "mod" | "index" | "app" | "program" | "startup" | "server" | "__init__" | "__main__"
+    ) || matches!(
+        name.as_str(),
+        "cargo.toml" | "package.json" | "pyproject.toml" | "go.mod" | "pom.xml" | "build.gradle"
+    ) || name.ends_with(".csproj")
+        || name.ends_with(".sln")
+    {
+        1
+    } else {
+        2
+    }
+}
+
+fn is_test(path: &Path, name: &str) -> bool {
+    let in_test_dir = path.parent().is_some_and(|parent| {
+        parent.components().any(|c| {
+            matches!(
+                c.as_os_str().to_string_lossy().to_lowercase().as_str(),
+                "test" | "tests" | "__tests__" | "spec" | "specs"
+            )
+        })
+    });
+    in_test_dir
+        || name.starts_with("test_")
+        || name.contains("_test.")
+        || name.contains(".test.")
+        || name.contains(".spec.")
+        || name.starts_with("tests.")
+        || name.contains("_tests.")
+        || name.contains(".tests.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(files: &[(&str, usize)]) -> Vec<Candidate> {
+        files
+            .iter()
+            .map(|&(path, tokens)| Candidate {
+                path: PathBuf::from(path),
+                size: tokens as u64 * 4,
+                tokens,
+            })
+            .collect()
+    }
+
+    fn budget(max_tokens: Option<usize>, priority: Priority) -> Budget {
+        Budget {
+            max_tokens,
+            max_bytes: None,
+            priority,
+            priority_globs: Vec::new(),
+        }
+    }
+
+    fn omitted(budget: &Budget, files: &[(&str, usize)]) -> Vec<String> {
+        budget
+            .omitted(&candidates(files))
+            .iter()
+            .map(|c| c.path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_everything_without_limits() {
+        assert!(omitted(&budget(None, Priority::Default), &[("a.rs", 1_000_000)]).is_empty());
+    }
+
+    #[test]
+    fn smaller_files_fill_the_rest_of_the_budget() {
+        let files = [("a.rs", 6), ("b.rs", 5), ("c.rs", 4)];
+        assert_eq!(omitted(&budget(Some(10), Priority::WalkOrder), &files), ["b.rs"]);
+    }
+
+    #[test]
+    fn default_priority_prefers_readmes_and_drops_tests() {
+        let files = [("tests/a_test.rs", 5), ("src/util.rs", 5), ("README.md", 5)];
+        assert_eq!(omitted(&budget(Some(10), Priority::Default), &files), ["tests/a_test.rs"]);
+    }
+
+    #[test]
+    fn recognizes_test_files() {
+        let is_test_file = |path: &str| is_test(Path::new(path), Path::new(path).file_name().unwrap().to_str().unwrap());
+        for path in ["src/tests.py", "app/views_tests.py", "lib/parser.tests.ts", "test_main.py", "spec/a.rb"] {
+            assert!(is_test_file(path), "{}", path);
+        }
+        for path in ["src/contests.py", "src/protests.rs", "pkg/attests.go", "src/latest.rs"] {
+            assert!(!is_test_file(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn smallest_first_fits_the_most_files() {
+        let files = [("big.rs", 9), ("a.rs", 3), ("b.rs", 3), ("c.rs", 3)];
+        assert_eq!(omitted(&budget(Some(9), Priority::SmallestFirst), &files), ["big.rs"]);
+    }
+}
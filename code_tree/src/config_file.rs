@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use clap::{parser::ValueSource, ArgMatches, ValueEnum};
+use serde::Deserialize;
+
+/// Names of the project config file, looked up in the root and its ancestors.
+pub const PROJECT_FILES: [&str; 2] = ["code_tree.toml", ".code_tree.toml"];
+
+/// Either `"rs,py"` or `["rs", "py"]`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum StringList {
+    Joined(String),
+    List(Vec<String>),
+}
+
+impl StringList {
+    pub fn joined(&self) -> String {
+        match self {
+            StringList::Joined(s) => s.clone(),
+            StringList::List(items) => items.join(","),
+        }
+    }
+
+    pub fn items(&self) -> Vec<String> {
+        match self {
+            StringList::Joined(s) => s.split(',').map(|s| s.to_string()).collect(),
+            StringList::List(items) => items.clone(),
+        }
+    }
+}
+
+/// Either a byte count or a string such as `"200KB"` or `"2000lines"`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum SizeValue {
+    Bytes(u64),
+    Text(String),
+}
+
+/// Settings from a `code_tree.toml`. Keys mirror the long command-line
+/// options; every key is optional.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub output: Option<PathBuf>,
+    pub format: Option<String>,
+    pub tree: Option<bool>,
+    pub tree_charset: Option<String>,
+    pub git_info: Option<bool>,
+    pub git_log: Option<usize>,
+    pub sort: Option<String>,
+    pub diff_context: Option<u32>,
+    pub diff_full: Option<bool>,
+    pub full_tree: Option<bool>,
+    pub ignored_dirs: Option<StringList>,
+    pub extensions: Option<StringList>,
+    pub include: Option<StringList>,
+    pub exclude: Option<StringList>,
+    pub max_tokens: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub priority: Option<String>,
+    pub priority_glob: Option<StringList>,
+    pub split_tokens: Option<usize>,
+    pub split_bytes: Option<usize>,
+    pub include_binary: Option<String>,
+    pub max_file_size: Option<SizeValue>,
+    pub oversized: Option<String>,
+    pub normalize_newlines: Option<bool>,
+    pub line_numbers: Option<bool>,
+    pub line_number_separator: Option<String>,
+    pub redact_secrets: Option<bool>,
+    pub fail_on_secrets: Option<bool>,
+    pub gitignore: Option<bool>,
+    pub tracked_only: Option<bool>,
+    pub tokenizer: Option<String>,
+    pub tokenizer_file: Option<PathBuf>,
+    pub jobs: Option<usize>,
+    pub stats: Option<bool>,
+    pub verbose: Option<bool>,
+    /// Named bundles of settings, selected with `--profile`.
+    pub profile: HashMap<String, Settings>,
+}
+
+macro_rules! overlay_fields {
+    ($base:ident, $top:ident, $($field:ident),* $(,)?) => {
+        $(
+            if $top.$field.is_some() {
+                $base.$field = $top.$field.clone();
+            }
+        )*
+    };
+}
+
+impl Settings {
+    /// Reads a config file, resolving relative paths against its directory.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut settings: Settings = toml::from_str(&text).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+        })?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        settings.resolve_paths(dir);
+        Ok(settings)
+    }
+
+    /// Layers `top` over `self`: every key set in `top` wins.
+    pub fn overlay(&mut self, top: &Settings) {
+        // The split limits are alternatives, so setting one replaces the other.
+        if top.split_tokens.is_some() || top.split_bytes.is_some() {
+            self.split_tokens = None;
+            self.split_bytes = None;
+        }
+        overlay_fields!(
+            self, top, output, format, tree, tree_charset, git_info, git_log, sort, diff_context,
+            diff_full, full_tree, ignored_dirs, extensions, include, exclude, max_tokens, max_bytes,
+            priority, priority_glob, split_tokens, split_bytes, include_binary, max_file_size,
+            oversized, normalize_newlines, line_numbers, line_number_separator, redact_secrets,
+            fail_on_secrets, gitignore, tracked_only, tokenizer, tokenizer_file, jobs, stats, verbose,
+        );
+        for (name, profile) in &top.profile {
+            self.profile
+                .entry(name.clone())
+                .or_default()
+                .overlay(profile);
+        }
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        if let Some(output) = &self.output {
+            self.output = Some(dir.join(output));
+        }
+        if let Some(tokenizer_file) = &self.tokenizer_file {
+            self.tokenizer_file = Some(dir.join(tokenizer_file));
+        }
+        for profile in self.profile.values_mut() {
+            profile.resolve_paths(dir);
+        }
+    }
+}
+
+/// The user-level config, e.g. `~/.config/code_tree/config.toml` on Linux.
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("code_tree").join("config.toml"))
+}
+
+/// Finds the nearest project config in `root` or one of its ancestors.
+pub fn find_project_config(root: &Path) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    root.ancestors()
+        .flat_map(|dir| PROJECT_FILES.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// Merges the settings from, lowest priority first: the user config, the
+/// project config (or `explicit` instead of it) and the named profile.
+pub fn resolve(root: &Path, explicit: Option<&Path>, profile: Option<&str>) -> io::Result<(Settings, Vec<PathBuf>)> {
+    let mut sources = Vec::new();
+    if let Some(path) = user_config_path().filter(|path| path.is_file()) {
+        sources.push(path);
+    }
+    match explicit {
+        Some(path) => sources.push(path.to_path_buf()),
+        None => sources.extend(find_project_config(root)),
+    }
+
+    let mut merged = Settings::default();
+    for path in &sources {
+        merged.overlay(&Settings::load(path)?);
+    }
+
+    if let Some(name) = profile {
+        let selected = merged.profile.get(name).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("profile `{}` is not defined in any config file", name),
+            )
+        })?;
+        merged.overlay(&selected);
+    }
+
+    Ok((merged, sources))
+}
+
+/// Whether the argument with clap id `id` was given on the command line.
+pub fn from_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+/// Parses a config value with the same names the command line accepts.
+pub fn parse_enum<T: ValueEnum>(key: &str, value: &str) -> io::Result<T> {
+    T::from_str(value, true).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid value `{}` for `{}` in config file", value, key),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(text: &str) -> Settings {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn accepts_lists_as_arrays_or_joined_strings() {
+        let parsed = settings("extensions = \"rs,py\"\nexclude = [\"a/**\", \"b\"]\nmax-file-size = \"200KB\"\n");
+        assert_eq!(parsed.extensions.unwrap().items(), ["rs", "py"]);
+        assert_eq!(parsed.exclude.unwrap().joined(), "a/**,b");
+        assert!(matches!(parsed.max_file_size, Some(SizeValue::Text(size)) if size == "200KB"));
+        assert!(toml::from_str::<Settings>("no-such-key = 1\n").is_err());
+    }
+
+    #[test]
+    fn later_layers_and_profiles_win() {
+        let mut merged = settings("format = \"markdown\"\nsplit-tokens = 100\n[profile.review]\ntree = false\n");
+        merged.overlay(&settings(
+            "format = \"xml\"\nsplit-bytes = 2000\n[profile.review]\nmax-tokens = 50\n",
+        ));
+        assert_eq!(merged.format.as_deref(), Some("xml"));
+        assert_eq!((merged.split_tokens, merged.split_bytes), (None, Some(2000)));
+
+        let review = merged.profile["review"].clone();
+        assert_eq!((review.tree, review.max_tokens), (Some(false), Some(50)));
+        merged.overlay(&review);
+        assert_eq!((merged.tree, merged.max_tokens, merged.format.as_deref()), (Some(false), Some(50), Some("xml")));
+    }
+
+    #[test]
+    fn resolves_the_config_file_and_profile() {
+        let dir = std::env::temp_dir().join(format!("code_tree-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("project/src")).unwrap();
+        fs::write(
+            dir.join("project/code_tree.toml"),
+            "output = \"out/context.md\"\nformat = \"markdown\"\n[profile.ci]\nfail-on-secrets = true\n",
+        )
+        .unwrap();
+
+        let nested = dir.join("project/src");
+        let (merged, sources) = resolve(&nested, None, Some("ci")).unwrap();
+        let project = dir.join("project").canonicalize().unwrap();
+        assert_eq!(sources.last(), Some(&project.join("code_tree.toml")));
+        assert_eq!(merged.output, Some(project.join("out/context.md")));
+        assert_eq!(merged.fail_on_secrets, Some(true));
+
+        let missing = resolve(&nested, None, Some("nightly")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::InvalidInput);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,649 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    str::FromStr,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chardetng::EncodingDetector;
+use clap::ValueEnum;
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::secrets::{Redaction, Redactor};
+
+/// How many leading bytes are inspected when sniffing for binary content.
+const SNIFF_LEN: usize = 8192;
+
+/// Size of the reads used to count the lines of an oversized file.
+const COUNT_BUFFER_LEN: usize = 64 * 1024;
+
+/// Signatures of common binary formats, checked against the start of a file.
+const MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xff\xd8\xff", "JPEG image"),
+    (b"GIF87a", "GIF image"),
+    (b"GIF89a", "GIF image"),
+    (b"II*\x00", "TIFF image"),
+    (b"MM\x00*", "TIFF image"),
+    (b"%PDF-", "PDF document"),
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"PK\x05\x06", "ZIP archive"),
+    (b"\x1f\x8b", "gzip archive"),
+    (b"\xfd7zXZ\x00", "xz archive"),
+    (b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (b"Rar!\x1a\x07", "RAR archive"),
+    (b"\x28\xb5\x2f\xfd", "zstd archive"),
+    (b"\x7fELF", "ELF executable"),
+    (b"\xfe\xed\xfa\xce", "Mach-O executable"),
+    (b"\xfe\xed\xfa\xcf", "Mach-O executable"),
+    (b"\xce\xfa\xed\xfe", "Mach-O executable"),
+    (b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+    (b"\xca\xfe\xba\xbe", "Java class or Mach-O universal binary"),
+    (b"\x00asm", "WebAssembly module"),
+    (b"SQLite format 3\x00", "SQLite database"),
+    (b"OggS", "Ogg media"),
+    (b"fLaC", "FLAC audio"),
+    (b"wOFF", "WOFF font"),
+    (b"wOF2", "WOFF2 font"),
+];
+
+/// Short signatures made of printable characters, which text can start with
+/// too (`MZ_LIMIT = 3`). They only name the format of content that looks
+/// binary anyway.
+const TEXT_LIKE_MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"MZ", "Windows executable"),
+    (b"BZh", "bzip2 archive"),
+    (b"ID3", "MP3 audio"),
+];
+
+/// How binary files are rendered when `--include-binary` is given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BinaryEncoding {
+    /// Offset, hex bytes and printable characters, 16 bytes per line
+    Hex,
+    /// Base64 wrapped at 76 columns
+    Base64,
+}
+
+/// Largest file emitted in full, e.g. `200KB`, `1MB`, `5000` (bytes) or `800lines`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeLimit {
+    Bytes(u64),
+    Lines(usize),
+}
+
+impl FromStr for SizeLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_lowercase();
+        let split = lower.find(|c: char| !c.is_ascii_digit()).unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(split);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("expected a number with an optional unit, got `{}`", s))?;
+
+        let multiplier = match unit.trim() {
+            "" | "b" => 1,
+            "k" | "kb" => 1024,
+            "m" | "mb" => 1024 * 1024,
+            "g" | "gb" => 1024 * 1024 * 1024,
+            "l" | "line" | "lines" => {
+                return usize::try_from(number)
+                    .map(SizeLimit::Lines)
+                    .map_err(|_| format!("`{}` is too large", s));
+            }
+            other => return Err(format!("unknown unit `{}` (use B, KB, MB, GB or lines)", other)),
+        };
+        number
+            .checked_mul(multiplier)
+            .map(SizeLimit::Bytes)
+            .ok_or_else(|| format!("`{}` is too large", s))
+    }
+}
+
+/// What happens to files over `--max-file-size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Oversized {
+    /// Leave the file out and list it in the trailer
+    Skip,
+    /// Keep the first lines
+    Head,
+    /// Keep the first and last lines
+    HeadTail,
+}
+
+/// How files are turned into text.
+#[derive(Clone, Debug)]
+pub struct LoadOptions {
+    /// Render binary files in this encoding instead of skipping them.
+    pub include_binary: Option<BinaryEncoding>,
+    /// Convert CRLF line endings to LF.
+    pub normalize_newlines: bool,
+    /// Prefix each line with its number followed by this separator.
+    pub line_numbers: Option<String>,
+    /// Replace secrets with placeholders.
+    pub redact: Option<Redactor>,
+    pub max_file_size: Option<SizeLimit>,
+    pub oversized: Oversized,
+}
+
+/// Lines dropped from an oversized file.
+#[derive(Clone, Copy, Debug)]
+pub struct Truncation {
+    pub omitted_lines: usize,
+    pub total_lines: usize,
+}
+
+/// The result of loading a code file.
+pub enum Loaded {
+    /// Contents ready to be written, with the encoding they were rendered from
+    /// if that was not plain UTF-8.
+    Text {
+        text: String,
+        encoding: Option<String>,
+        truncation: Option<Truncation>,
+        /// Secrets replaced in the lines that were read, which may include
+        /// some that were then truncated.
+        redactions: Vec<Redaction>,
+    },
+    /// A file that should be left out, with the reason (binary, too large).
+    Skipped(String),
+}
+
+/// Reads `path` and decodes it to UTF-8.
+///
+/// A byte order mark decides the encoding when present. Otherwise binary
+/// content is detected and skipped unless `include_binary` says how to render
+/// it, and anything that is not valid UTF-8 is transcoded from its most likely
+/// legacy encoding (e.g. windows-1252 or Shift_JIS). Secrets are redacted and
+/// lines numbered before files over `max_file_size` are skipped or truncated,
+/// so the kept lines keep their original numbers. Files over a byte limit are
+/// never read in full.
+pub fn load(path: &Path, options: &LoadOptions) -> io::Result<Loaded> {
+    if let Some(SizeLimit::Bytes(limit)) = options.max_file_size {
+        let size = fs::metadata(path)?.len();
+        if size > limit {
+            match options.oversized {
+                Oversized::Skip => return Ok(Loaded::Skipped(format!("too large: {} bytes", size))),
+                strategy => {
+                    if let Some(loaded) = load_ends(path, limit, strategy, options)? {
+                        return Ok(loaded);
+                    }
+                }
+            }
+        }
+    }
+
+    let bytes = fs::read(path)?;
+
+    let (mut text, encoding) = match decode(bytes, options.include_binary) {
+        Decoded::Text(text, encoding) => (text, encoding),
+        Decoded::Binary(kind) => return Ok(Loaded::Skipped(format!("binary: {}", kind))),
+    };
+    let mut redactions = Vec::new();
+    text = prepare(text, 1, 0, options, &mut redactions);
+
+    let Some(limit) = options.max_file_size else {
+        return Ok(Loaded::Text { text, encoding, truncation: None, redactions });
+    };
+    Ok(match truncate(text, limit, options.oversized) {
+        Ok((text, truncation)) => Loaded::Text { text, encoding, truncation, redactions },
+        Err(reason) => Loaded::Skipped(reason),
+    })
+}
+
+/// Loads only the ends of a file over a byte `limit`: its first bytes, and
+/// its last ones with `HeadTail`. The lines in between are counted, not kept.
+/// Returns `None` for binary files rendered with `include_binary`, which are
+/// loaded in full.
+fn load_ends(path: &Path, limit: u64, strategy: Oversized, options: &LoadOptions) -> io::Result<Option<Loaded>> {
+    let (head_max, tail_max) = match strategy {
+        Oversized::HeadTail => (limit - limit / 2, limit / 2),
+        _ => (limit, 0),
+    };
+
+    let mut file = File::open(path)?;
+    let mut head = Vec::new();
+    (&mut file).take(head_max).read_to_end(&mut head)?;
+    let mut newlines = count_newlines(&head);
+    let mut last = head.last().copied();
+    let mut buffer = vec![0; COUNT_BUFFER_LEN];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        newlines += count_newlines(&buffer[..read]);
+        last = Some(buffer[read - 1]);
+    }
+    let total_lines = newlines + usize::from(last.is_some_and(|b| b != b'\n'));
+
+    let mut tail = Vec::new();
+    if tail_max > 0 {
+        let end = file.stream_position()?;
+        file.seek(SeekFrom::Start(end.saturating_sub(tail_max).max(head.len() as u64)))?;
+        file.take(tail_max).read_to_end(&mut tail)?;
+    }
+
+    // Only whole lines are kept, unless a single line is longer than the window
+    let head_is_whole = head.contains(&b'\n');
+    match head.iter().rposition(|&b| b == b'\n') {
+        Some(end) => head.truncate(end + 1),
+        None => {
+            if let Err(e) = std::str::from_utf8(&head) {
+                if e.error_len().is_none() {
+                    head.truncate(e.valid_up_to());
+                }
+            }
+        }
+    }
+    let body = tail.len().saturating_sub(1);
+    let tail_is_whole = tail[..body].contains(&b'\n');
+    let start = match tail[..body].iter().position(|&b| b == b'\n') {
+        Some(newline) => newline + 1,
+        None => tail.iter().take(3).take_while(|&&b| b & 0xc0 == 0x80).count(),
+    };
+    tail.drain(..start);
+
+    let (head, encoding) = match decode(head, None) {
+        Decoded::Text(text, encoding) => (text, encoding),
+        Decoded::Binary(_) if options.include_binary.is_some() => return Ok(None),
+        Decoded::Binary(kind) => return Ok(Some(Loaded::Skipped(format!("binary: {}", kind)))),
+    };
+    let tail = match decode(tail, None) {
+        Decoded::Text(text, _) => text,
+        Decoded::Binary(_) => String::new(),
+    };
+
+    let width = total_lines.to_string().len();
+    let mut redactions = Vec::new();
+    let head = prepare(head, 1, width, options, &mut redactions);
+    let tail_first_line = (total_lines + 1).saturating_sub(tail.split_inclusive('\n').count()).max(1);
+    let tail = prepare(tail, tail_first_line, width, options, &mut redactions);
+
+    // Line numbers may have made the ends longer than their share
+    let head_lines: Vec<&str> = head.split_inclusive('\n').collect();
+    let tail_lines: Vec<&str> = tail.split_inclusive('\n').collect();
+    let (head, head_count) = head_within(&head_lines, head_max);
+    let (tail, tail_count) = tail_within(&tail_lines, tail_max);
+    let head_count = if head_is_whole { head_count } else { 0 };
+    let tail_count = if tail_is_whole { tail_count } else { 0 };
+    let omitted_lines = total_lines.saturating_sub(head_count + tail_count);
+    let text = join_ends(head, &tail, omitted_lines);
+
+    Ok(Some(Loaded::Text {
+        text,
+        encoding,
+        truncation: Some(Truncation { omitted_lines, total_lines }),
+        redactions,
+    }))
+}
+
+/// Normalizes newlines, redacts secrets and numbers lines from `first_line`,
+/// padding the numbers to `width` or to the widest one in `text` if larger.
+fn prepare(mut text: String, first_line: usize, width: usize, options: &LoadOptions, redactions: &mut Vec<Redaction>) -> String {
+    if options.normalize_newlines {
+        text = text.replace("\r\n", "\n");
+    }
+    if let Some(redactor) = &options.redact {
+        let found;
+        (text, found) = redactor.redact(&text);
+        redactions.extend(found.into_iter().map(|redaction| Redaction {
+            line: redaction.line + first_line - 1,
+            ..redaction
+        }));
+    }
+    if let Some(separator) = &options.line_numbers {
+        text = number_lines(&text, first_line, width, separator);
+    }
+    text
+}
+
+fn count_newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b == b'\n').count()
+}
+
+enum Decoded {
+    Text(String, Option<String>),
+    Binary(&'static str),
+}
+
+fn decode(bytes: Vec<u8>, include_binary: Option<BinaryEncoding>) -> Decoded {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
+        let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        let encoding = (encoding != UTF_8).then(|| describe(encoding, had_errors));
+        return Decoded::Text(text.into_owned(), encoding);
+    }
+
+    if let Some(kind) = binary_kind(&bytes) {
+        return match include_binary {
+            Some(BinaryEncoding::Hex) => Decoded::Text(hex_dump(&bytes), Some(format!("{}, hex", kind))),
+            Some(BinaryEncoding::Base64) => {
+                Decoded::Text(base64_lines(&bytes), Some(format!("{}, base64", kind)))
+            }
+            None => Decoded::Binary(kind),
+        };
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(text) => Decoded::Text(text, None),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            let mut detector = EncodingDetector::new();
+            detector.feed(&bytes, true);
+            let encoding = detector.guess(None, true);
+
+            let (text, had_errors) = encoding.decode_without_bom_handling(&bytes);
+            Decoded::Text(text.into_owned(), Some(describe(encoding, had_errors)))
+        }
+    }
+}
+
+/// Cuts `text` down to `limit` on line boundaries, replacing the dropped lines
+/// with a `[... truncated N lines ...]` marker. Returns the skip reason instead
+/// when the strategy is to skip.
+fn truncate(text: String, limit: SizeLimit, strategy: Oversized) -> Result<(String, Option<Truncation>), String> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let total_lines = lines.len();
+
+    let fits = match limit {
+        SizeLimit::Bytes(max) => text.len() as u64 <= max,
+        SizeLimit::Lines(max) => total_lines <= max,
+    };
+    if fits {
+        return Ok((text, None));
+    }
+
+    let (head, head_count, tail, tail_count) = match (strategy, limit) {
+        (Oversized::Skip, SizeLimit::Bytes(_)) => return Err(format!("too large: {} bytes", text.len())),
+        (Oversized::Skip, SizeLimit::Lines(_)) => return Err(format!("too large: {} lines", total_lines)),
+        (Oversized::Head, SizeLimit::Lines(max)) => (lines[..max].concat(), max, String::new(), 0),
+        (Oversized::Head, SizeLimit::Bytes(max)) => {
+            let (head, head_count) = head_within(&lines, max);
+            (head, head_count, String::new(), 0)
+        }
+        (Oversized::HeadTail, SizeLimit::Lines(max)) => {
+            let (head, tail) = (max - max / 2, max / 2);
+            (lines[..head].concat(), head, lines[total_lines - tail..].concat(), tail)
+        }
+        (Oversized::HeadTail, SizeLimit::Bytes(max)) => {
+            let (head, head_count) = head_within(&lines, max - max / 2);
+            let (tail, tail_count) = tail_within(&lines[head_count..], max / 2);
+            (head, head_count, tail, tail_count)
+        }
+    };
+    let omitted_lines = total_lines - head_count - tail_count;
+
+    Ok((join_ends(head, &tail, omitted_lines), Some(Truncation { omitted_lines, total_lines })))
+}
+
+/// Joins the kept ends of a file around a `[... truncated N lines ...]` marker.
+fn join_ends(mut head: String, tail: &str, omitted_lines: usize) -> String {
+    if !head.is_empty() && !head.ends_with('\n') {
+        head.push('\n');
+    }
+    head.push_str(&format!("[... truncated {} lines ...]\n", omitted_lines));
+    head.push_str(tail);
+    head
+}
+
+/// Prefixes each line with its number, counting from `first_line` and
+/// right-aligned to `width` or to the width of the largest number if larger.
+fn number_lines(text: &str, first_line: usize, width: usize, separator: &str) -> String {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let width = width.max((first_line + lines.len()).saturating_sub(1).to_string().len());
+
+    let mut numbered = String::with_capacity(text.len() + lines.len() * (width + separator.len()));
+    for (index, line) in lines.iter().enumerate() {
+        numbered.push_str(&format!("{:>width$}{}{}", first_line + index, separator, line, width = width));
+    }
+    numbered
+}
+
+/// The first of `lines` that fit in `max` bytes and how many there are. When
+/// not even the first line fits, as much of it as does, cut on a character
+/// boundary, which counts as no whole line.
+fn head_within(lines: &[&str], max: u64) -> (String, usize) {
+    let count = lines_within(lines.iter(), max);
+    match lines.first() {
+        Some(first) if count == 0 => {
+            let mut end = (max as usize).min(first.len());
+            while !first.is_char_boundary(end) {
+                end -= 1;
+            }
+            (first[..end].to_string(), 0)
+        }
+        _ => (lines[..count].concat(), count),
+    }
+}
+
+/// The last of `lines` that fit in `max` bytes and how many there are, or the
+/// end of the last line when it does not fit on its own.
+fn tail_within(lines: &[&str], max: u64) -> (String, usize) {
+    let count = lines_within(lines.iter().rev(), max);
+    match lines.last() {
+        Some(last) if count == 0 && max > 0 => {
+            let mut start = last.len().saturating_sub(max as usize);
+            while !last.is_char_boundary(start) {
+                start += 1;
+            }
+            (last[start..].to_string(), 0)
+        }
+        _ => (lines[lines.len() - count..].concat(), count),
+    }
+}
+
+/// How many of `lines`, taken in order, fit in `max` bytes.
+fn lines_within<'a>(lines: impl Iterator<Item = &'a &'a str>, max: u64) -> usize {
+    let mut used = 0;
+    lines
+        .take_while(|line| {
+            used += line.len() as u64;
+            used <= max
+        })
+        .count()
+}
+
+fn describe(encoding: &'static Encoding, had_errors: bool) -> String {
+    if had_errors {
+        format!("{}, with invalid bytes replaced", encoding.name())
+    } else {
+        encoding.name().to_string()
+    }
+}
+
+/// Describes the binary format of `bytes`, or returns `None` if they look like text.
+pub fn binary_kind(bytes: &[u8]) -> Option<&'static str> {
+    if let Some((_, kind)) = MAGIC_NUMBERS.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        return Some(kind);
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("WebP image");
+    }
+
+    let head = &bytes[..bytes.len().min(SNIFF_LEN)];
+    // Text rarely contains control characters other than whitespace and escapes.
+    let control = head
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    if !head.contains(&0) && (head.is_empty() || control * 10 <= head.len()) {
+        return None;
+    }
+
+    let known = TEXT_LIKE_MAGIC_NUMBERS.iter().find(|(magic, _)| bytes.starts_with(magic));
+    Some(known.map_or("binary data", |(_, kind)| kind))
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        dump.push_str(&format!("{:08x}  {:<47}  |{}|\n", line * 16, hex.join(" "), ascii));
+    }
+    dump
+}
+
+fn base64_lines(bytes: &[u8]) -> String {
+    let encoded = STANDARD.encode(bytes);
+    let mut lines = String::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+    for line in encoded.as_bytes().chunks(76) {
+        lines.push_str(std::str::from_utf8(line).unwrap_or_default());
+        lines.push('\n');
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_binary_formats() {
+        assert_eq!(binary_kind(b"\x89PNG\r\n\x1a\n\0\0"), Some("PNG image"));
+        assert_eq!(binary_kind(b"MZ\x90\0\x03\0\0\0\x04\0"), Some("Windows executable"));
+        assert_eq!(binary_kind(b"BZh91AY&SY\0\x01\x02"), Some("bzip2 archive"));
+        assert_eq!(binary_kind(b"plain\0text"), Some("binary data"));
+    }
+
+    #[test]
+    fn text_may_start_like_a_short_signature() {
+        assert_eq!(binary_kind(b"MZ_LIMIT = 3\nBZ_LEVEL = 9\n"), None);
+        assert_eq!(binary_kind(b"BZh is the bzip2 magic number\n"), None);
+        assert_eq!(binary_kind(b"ID3 tags hold the title and artist\n"), None);
+    }
+
+    fn decoded(bytes: &[u8]) -> (String, Option<String>) {
+        match decode(bytes.to_vec(), None) {
+            Decoded::Text(text, encoding) => (text, encoding),
+            Decoded::Binary(kind) => panic!("binary: {}", kind),
+        }
+    }
+
+    #[test]
+    fn transcodes_to_utf8_and_names_the_encoding() {
+        assert_eq!(decoded("// café\n".as_bytes()), ("// café\n".to_string(), None));
+        assert_eq!(decoded(b"\xef\xbb\xbf// caf\xc3\xa9\n"), ("// café\n".to_string(), None));
+        assert_eq!(
+            decoded(b"\xff\xfe/\0/\0 \0c\0a\0f\0\xe9\0\n\0"),
+            ("// café\n".to_string(), Some("UTF-16LE".to_string()))
+        );
+        assert_eq!(
+            decoded(b"// Le caf\xe9 est tr\xe8s bon, \xe0 bient\xf4t.\n"),
+            ("// Le café est très bon, à bientôt.\n".to_string(), Some("windows-1252".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_size_limits() {
+        assert_eq!("200KB".parse(), Ok(SizeLimit::Bytes(200 * 1024)));
+        assert_eq!("5000".parse(), Ok(SizeLimit::Bytes(5000)));
+        assert_eq!(" 1 mb ".parse(), Ok(SizeLimit::Bytes(1024 * 1024)));
+        assert_eq!("2000lines".parse(), Ok(SizeLimit::Lines(2000)));
+        assert_eq!("10 line".parse(), Ok(SizeLimit::Lines(10)));
+    }
+
+    #[test]
+    fn rejects_bad_size_limits() {
+        assert!("12parsecs".parse::<SizeLimit>().unwrap_err().contains("unknown unit"));
+        assert!("KB".parse::<SizeLimit>().is_err());
+        assert!("".parse::<SizeLimit>().is_err());
+    }
+
+    const FIVE_LINES: &str = "1\n2\n3\n4\n5\n";
+
+    #[test]
+    fn keeps_text_that_fits() {
+        let (text, truncation) = truncate(FIVE_LINES.to_string(), SizeLimit::Lines(5), Oversized::Head).unwrap();
+        assert_eq!(text, FIVE_LINES);
+        assert!(truncation.is_none());
+    }
+
+    #[test]
+    fn truncates_to_head_lines() {
+        let (text, truncation) = truncate(FIVE_LINES.to_string(), SizeLimit::Lines(2), Oversized::Head).unwrap();
+        assert_eq!(text, "1\n2\n[... truncated 3 lines ...]\n");
+        let truncation = truncation.unwrap();
+        assert_eq!((truncation.omitted_lines, truncation.total_lines), (3, 5));
+    }
+
+    #[test]
+    fn truncates_to_head_and_tail() {
+        let (text, _) = truncate(FIVE_LINES.to_string(), SizeLimit::Lines(2), Oversized::HeadTail).unwrap();
+        assert_eq!(text, "1\n[... truncated 3 lines ...]\n5\n");
+    }
+
+    #[test]
+    fn truncates_on_line_boundaries_within_bytes() {
+        let (text, _) = truncate(FIVE_LINES.to_string(), SizeLimit::Bytes(5), Oversized::Head).unwrap();
+        assert_eq!(text, "1\n2\n[... truncated 3 lines ...]\n");
+    }
+
+    #[test]
+    fn cuts_a_long_line_on_a_char_boundary() {
+        let (text, truncation) = truncate("ééééé".to_string(), SizeLimit::Bytes(5), Oversized::Head).unwrap();
+        assert_eq!(text, "éé\n[... truncated 1 lines ...]\n");
+        assert_eq!(truncation.unwrap().omitted_lines, 1);
+    }
+
+    fn load_oversized(name: &str, contents: &str, limit: u64, oversized: Oversized) -> (String, Truncation) {
+        let path = std::env::temp_dir().join(format!("code_tree-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let options = LoadOptions {
+            include_binary: None,
+            normalize_newlines: false,
+            line_numbers: Some(": ".to_string()),
+            redact: None,
+            max_file_size: Some(SizeLimit::Bytes(limit)),
+            oversized,
+        };
+        let loaded = load(&path, &options);
+        fs::remove_file(&path).unwrap();
+        match loaded.unwrap() {
+            Loaded::Text { text, truncation, .. } => (text, truncation.unwrap()),
+            Loaded::Skipped(reason) => panic!("skipped: {}", reason),
+        }
+    }
+
+    #[test]
+    fn loads_only_the_ends_of_oversized_files() {
+        let contents: String = (1..=1000).map(|i| format!("line {}\n", i)).collect();
+        let (text, truncation) = load_oversized("ends", &contents, 64, Oversized::HeadTail);
+        assert_eq!(text, "   1: line 1\n   2: line 2\n[... truncated 996 lines ...]\n 999: line 999\n1000: line 1000\n");
+        assert_eq!((truncation.omitted_lines, truncation.total_lines), (996, 1000));
+    }
+
+    #[test]
+    fn keeps_the_start_of_a_single_oversized_line() {
+        let (text, truncation) = load_oversized("bundle", &"var a=1;".repeat(5000), 1024, Oversized::Head);
+        assert!(text.starts_with("1: var a=1;var a=1;"));
+        assert!(text.ends_with("\n[... truncated 1 lines ...]\n"));
+        assert!(text.len() < 1024 + 40);
+        assert_eq!((truncation.omitted_lines, truncation.total_lines), (1, 1));
+    }
+
+    #[test]
+    fn skips_oversized_text() {
+        let reason = truncate(FIVE_LINES.to_string(), SizeLimit::Lines(2), Oversized::Skip).unwrap_err();
+        assert_eq!(reason, "too large: 5 lines");
+    }
+
+    #[test]
+    fn numbers_lines_right_aligned() {
+        let text = "a\n".repeat(9) + "b";
+        let numbered = number_lines(&text, 1, 0, " | ");
+        assert!(numbered.starts_with(" 1 | a\n 2 | a\n"));
+        assert!(numbered.ends_with("\n10 | b"));
+    }
+
+    #[test]
+    fn rejects_overflowing_size_limits() {
+        assert!("99999999999GB".parse::<SizeLimit>().unwrap_err().contains("too large"));
+        assert!("99999999999999999999".parse::<SizeLimit>().is_err());
+    }
+}
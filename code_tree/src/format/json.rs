@@ -0,0 +1,242 @@
+use std::io::{self, Write};
+use serde::Serialize;
+
+use super::{Chunk, FileRecord, Omitted, OutputWriter, Summary, TreeEntry};
+use crate::git::RepoInfo;
+use crate::language::language_for;
+
+#[derive(Serialize)]
+struct JsonTreeEntry {
+    path: String,
+    depth: usize,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct JsonRepository<'a> {
+    name: &'a str,
+    commit: Option<&'a str>,
+    branch: Option<&'a str>,
+    dirty: bool,
+    recent_commits: Vec<JsonCommit<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonCommit<'a> {
+    id: &'a str,
+    subject: &'a str,
+}
+
+impl<'a> From<&'a RepoInfo> for JsonRepository<'a> {
+    fn from(repo: &'a RepoInfo) -> Self {
+        JsonRepository {
+            name: &repo.name,
+            commit: repo.commit.as_deref(),
+            branch: repo.branch.as_deref(),
+            dirty: repo.dirty,
+            recent_commits: repo
+                .recent_commits
+                .iter()
+                .map(|commit| JsonCommit {
+                    id: &commit.id,
+                    subject: &commit.subject,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonChunk {
+    index: usize,
+    count: usize,
+    first_line: usize,
+    last_line: usize,
+}
+
+impl From<&Chunk> for JsonChunk {
+    fn from(chunk: &Chunk) -> Self {
+        JsonChunk {
+            index: chunk.index,
+            count: chunk.count,
+            first_line: chunk.first_line,
+            last_line: chunk.last_line,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonTruncation {
+    omitted_lines: usize,
+    total_lines: usize,
+}
+
+#[derive(Serialize)]
+struct JsonOmitted {
+    path: String,
+    omitted: bool,
+    reason: String,
+}
+
+impl JsonOmitted {
+    fn new(file: &Omitted) -> Self {
+        JsonOmitted {
+            path: file.path.to_string_lossy().into_owned(),
+            omitted: true,
+            reason: file.reason.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonFile<'a> {
+    path: String,
+    size: u64,
+    extension: Option<String>,
+    line_count: usize,
+    tokens: usize,
+    language: Option<&'static str>,
+    contents: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    renamed_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<JsonTruncation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<JsonChunk>,
+}
+
+impl<'a> JsonFile<'a> {
+    fn new(file: &'a FileRecord) -> Self {
+        JsonFile {
+            path: file.path.to_string_lossy().into_owned(),
+            size: file.size,
+            extension: file.extension(),
+            line_count: file.line_count(),
+            tokens: file.tokens,
+            language: language_for(file.path),
+            contents: file.contents.as_ref().and_then(|contents| contents.as_deref().ok()),
+            error: file.contents.as_ref().and_then(|contents| contents.as_ref().err()).map(|e| e.to_string()),
+            change: file.change.map(|change| change.name()),
+            renamed_from: file.renamed_from.as_ref().map(|old| old.to_string_lossy().into_owned()),
+            diff: file.diff.as_deref(),
+            encoding: file.encoding.as_deref(),
+            truncated: file.truncation.map(|t| JsonTruncation {
+                omitted_lines: t.omitted_lines,
+                total_lines: t.total_lines,
+            }),
+            chunk: file.chunk.as_ref().map(JsonChunk::from),
+        }
+    }
+}
+
+/// Writes one JSON document: the summary fields, a `tree` array, a `files`
+/// array and an `omitted` array. Files are streamed one per line rather than
+/// buffered.
+#[derive(Default)]
+pub struct JsonWriter {
+    files_written: usize,
+}
+
+impl OutputWriter for JsonWriter {
+    fn write_header(&mut self, out: &mut dyn Write, summary: &Summary) -> io::Result<()> {
+        write!(
+            out,
+            "{{\"root\":{},\"total_files\":{},\"code_files\":{},\"total_tokens\":{},\"tokenizer\":{},",
+            serde_json::to_string(&summary.root.to_string_lossy())?,
+            summary.total_files,
+            summary.code_files,
+            summary.total_tokens,
+            serde_json::to_string(summary.tokenizer)?,
+        )?;
+        if let Some(part) = summary.part {
+            write!(out, "\"part\":{},", part)?;
+        }
+        if let Some(repo) = summary.repository {
+            write!(out, "\"repository\":")?;
+            serde_json::to_writer(&mut *out, &JsonRepository::from(repo))?;
+            write!(out, ",")?;
+        }
+        Ok(())
+    }
+
+    fn write_tree(&mut self, out: &mut dyn Write, tree: &[TreeEntry]) -> io::Result<()> {
+        let tree: Vec<JsonTreeEntry> = tree
+            .iter()
+            .map(|entry| JsonTreeEntry {
+                path: entry.path.to_string_lossy().into_owned(),
+                depth: entry.depth,
+                kind: if entry.is_dir { "directory" } else { "file" },
+                tokens: entry.tokens,
+                change: entry.change.map(|change| change.name()),
+            })
+            .collect();
+        write!(out, "\"tree\":")?;
+        serde_json::to_writer(&mut *out, &tree)?;
+        write!(out, ",")
+    }
+
+    fn begin_contents(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "\"files\":[")
+    }
+
+    fn write_file(&mut self, out: &mut dyn Write, file: &FileRecord) -> io::Result<()> {
+        if self.files_written > 0 {
+            write!(out, ",")?;
+        }
+        writeln!(out)?;
+        serde_json::to_writer(&mut *out, &JsonFile::new(file))?;
+        self.files_written += 1;
+        Ok(())
+    }
+
+    fn write_omitted(&mut self, out: &mut dyn Write, omitted: &[Omitted]) -> io::Result<()> {
+        let omitted: Vec<JsonOmitted> = omitted.iter().map(JsonOmitted::new).collect();
+        write!(out, "\n],\"omitted\":")?;
+        serde_json::to_writer(&mut *out, &omitted)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "}}")
+    }
+}
+
+/// Writes one JSON record per included file, followed by one record per
+/// omitted file.
+pub struct JsonLinesWriter;
+
+impl OutputWriter for JsonLinesWriter {
+    fn write_header(&mut self, _out: &mut dyn Write, _summary: &Summary) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_tree(&mut self, _out: &mut dyn Write, _tree: &[TreeEntry]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_file(&mut self, out: &mut dyn Write, file: &FileRecord) -> io::Result<()> {
+        serde_json::to_writer(&mut *out, &JsonFile::new(file))?;
+        writeln!(out)
+    }
+
+    fn write_omitted(&mut self, out: &mut dyn Write, omitted: &[Omitted]) -> io::Result<()> {
+        for file in omitted {
+            serde_json::to_writer(&mut *out, &JsonOmitted::new(file))?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::{self, Write};
+
+use super::{FileRecord, Omitted, OutputWriter, Summary, TreeEntry};
+use crate::language::language_for;
+
+/// Markdown layout: the tree in a fenced block and one fenced, language-tagged
+/// block per file.
+pub struct MarkdownWriter;
+
+impl OutputWriter for MarkdownWriter {
+    fn write_header(&mut self, out: &mut dyn Write, summary: &Summary) -> io::Result<()> {
+        writeln!(out, "# Directory Tree and Code Contents\n")?;
+        if let Some(part) = summary.part {
+            writeln!(out, "- **Part:** {}", part)?;
+        }
+        writeln!(out, "- **Root Directory:** `{}`", summary.root.display())?;
+        writeln!(out, "- **Total Files:** {}", summary.total_files)?;
+        writeln!(out, "- **Code Files:** {}", summary.code_files)?;
+        writeln!(out, "- **Total Tokens:** {} ({})", summary.total_tokens, summary.tokenizer)?;
+        if let Some(repo) = summary.repository {
+            writeln!(out, "- **Repository:** `{}`", repo.name)?;
+            writeln!(out, "- **Commit:** {}", repo.describe())?;
+            if !repo.recent_commits.is_empty() {
+                writeln!(out, "- **Recent Commits:**")?;
+                for commit in &repo.recent_commits {
+                    writeln!(out, "  - `{}` {}", commit.id, commit.subject)?;
+                }
+            }
+        }
+        writeln!(out)
+    }
+
+    fn write_tree(&mut self, out: &mut dyn Write, tree: &[TreeEntry]) -> io::Result<()> {
+        let lines: Vec<String> = tree.iter().map(TreeEntry::line).collect();
+        let fence = fence_for(&lines.join("\n"));
+
+        writeln!(out, "## Directory Tree\n")?;
+        writeln!(out, "{}text", fence)?;
+        for line in &lines {
+            writeln!(out, "{}", line)?;
+        }
+        writeln!(out, "{}", fence)
+    }
+
+    fn begin_contents(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "\n## Code Contents")
+    }
+
+    fn write_file(&mut self, out: &mut dyn Write, file: &FileRecord) -> io::Result<()> {
+        match file.label() {
+            Some(label) => writeln!(out, "\n### `{}` ({})\n", file.path.display(), label)?,
+            None => writeln!(out, "\n### `{}`\n", file.path.display())?,
+        }
+
+        if let Some(diff) = &file.diff {
+            write_block(out, "diff", diff)?;
+        }
+        match &file.contents {
+            Some(Ok(contents)) => {
+                if file.diff.is_some() {
+                    writeln!(out)?;
+                }
+                write_block(out, language_for(file.path).unwrap_or(""), contents)
+            }
+            Some(Err(e)) => writeln!(out, "> Error reading file: {}", e),
+            None => Ok(()),
+        }
+    }
+
+    fn write_omitted(&mut self, out: &mut dyn Write, omitted: &[Omitted]) -> io::Result<()> {
+        if omitted.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "\n## Omitted Files\n")?;
+        for file in omitted {
+            writeln!(out, "- `{}` ({})", file.path.display(), file.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `contents` in a fenced block tagged with `language`.
+fn write_block(out: &mut dyn Write, language: &str, contents: &str) -> io::Result<()> {
+    let fence = fence_for(contents);
+    writeln!(out, "{}{}", fence, language)?;
+    write!(out, "{}", contents)?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        writeln!(out)?;
+    }
+    writeln!(out, "{}", fence)
+}
+
+/// Returns a backtick fence longer than any backtick run in `contents`, so the
+/// contents can never close the block early.
+fn fence_for(contents: &str) -> String {
+    let longest_run = contents
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat((longest_run + 1).max(3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fence_outlasts_backtick_runs() {
+        assert_eq!(fence_for("no ticks"), "```");
+        assert_eq!(fence_for("`inline` and ``double``"), "```");
+        assert_eq!(fence_for("````\nnested\n````"), "`````");
+    }
+}
@@ -0,0 +1,294 @@
+mod json;
+mod markdown;
+mod text;
+mod xml;
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use clap::ValueEnum;
+
+use crate::content::Truncation;
+use crate::git::{ChangeKind, RepoInfo};
+
+pub use json::{JsonLinesWriter, JsonWriter};
+pub use markdown::MarkdownWriter;
+pub use text::TextWriter;
+pub use xml::XmlWriter;
+
+/// Layout of the generated output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain text with `=== File: path ===` separators
+    Text,
+    /// Markdown with fenced, language-tagged code blocks
+    Markdown,
+    /// A single JSON document with the summary, tree and files
+    Json,
+    /// One JSON record per file
+    Jsonl,
+    /// XML-like `<document>` tags, as recommended for LLM prompts
+    Xml,
+}
+
+/// Creates a fresh writer for each output part. Use this to plug in a custom
+/// [`OutputWriter`].
+pub type WriterFactory = Arc<dyn Fn() -> Box<dyn OutputWriter> + Send + Sync>;
+
+impl OutputFormat {
+    pub fn factory(self) -> WriterFactory {
+        Arc::new(move || self.writer())
+    }
+
+    pub fn writer(self) -> Box<dyn OutputWriter> {
+        match self {
+            OutputFormat::Text => Box::new(TextWriter),
+            OutputFormat::Markdown => Box::new(MarkdownWriter),
+            OutputFormat::Json => Box::new(JsonWriter::default()),
+            OutputFormat::Jsonl => Box::new(JsonLinesWriter),
+            OutputFormat::Xml => Box::new(XmlWriter),
+        }
+    }
+}
+
+/// Leading keys of a JSON Lines record. That format has no header, so its
+/// dumps are recognized by their first record instead.
+const JSONL_KEYS: &[&str] = &["{\"path\":", "\"extension\":", "\"line_count\":", "\"tokens\":"];
+
+/// How many leading bytes `is_generated` needs to see.
+pub const MARKER_LEN: usize = 512;
+
+/// Whether a file starting with `head` is output written by this tool, going
+/// by the title and the header lines that follow it, so that a file merely
+/// opening with the same title is not mistaken for a dump.
+pub fn is_generated(head: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(head);
+    if head.starts_with("{\"root\":") {
+        return head.contains(",\"total_files\":") && head.contains(",\"tokenizer\":");
+    }
+    if head.starts_with("{\"path\":") {
+        let first_line = head.lines().next().unwrap_or_default();
+        return JSONL_KEYS.iter().all(|key| first_line.contains(key))
+            || first_line.contains(",\"omitted\":true,");
+    }
+    if let Some(rest) = head.strip_prefix("<code_tree root=\"") {
+        let tag = rest.lines().next().unwrap_or_default();
+        return tag.contains("\" total_files=\"") && tag.contains("\" tokenizer=\"");
+    }
+    if let Some(rest) = head.strip_prefix("Directory Tree and Code Contents\n\n") {
+        let rest = skip_part(rest, "Part: ", "\n\n");
+        return rest.starts_with("Root Directory: ") && rest.contains("\n\nTotal Files: ");
+    }
+    if let Some(rest) = head.strip_prefix("# Directory Tree and Code Contents\n\n") {
+        let rest = skip_part(rest, "- **Part:** ", "\n");
+        return rest.starts_with("- **Root Directory:** `") && rest.contains("`\n- **Total Files:** ");
+    }
+    false
+}
+
+/// Skips the part number line that split output has before the root.
+fn skip_part<'a>(text: &'a str, label: &str, end: &str) -> &'a str {
+    text.strip_prefix(label)
+        .and_then(|rest| rest.split_once(end))
+        .filter(|(number, _)| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+        .map_or(text, |(_, rest)| rest)
+}
+
+/// Figures shown at the top of the output.
+#[derive(Clone, Copy)]
+pub struct Summary<'a> {
+    pub root: &'a Path,
+    pub total_files: usize,
+    pub code_files: usize,
+    pub total_tokens: usize,
+    pub tokenizer: &'a str,
+    /// The 1-based part number when the output is split.
+    pub part: Option<usize>,
+    /// The git repository the files came from, when asked for.
+    pub repository: Option<&'a RepoInfo>,
+}
+
+/// A single entry of the directory tree section.
+pub struct TreeEntry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub name: String,
+    pub is_dir: bool,
+    /// Tokens in the file, or in the included files below the directory.
+    pub tokens: Option<usize>,
+    /// Guides and connector drawn before the name, filled in by `tree::arrange`.
+    pub prefix: String,
+    /// How the file changed, when only changed files are included.
+    pub change: Option<ChangeKind>,
+}
+
+impl TreeEntry {
+    pub fn line(&self) -> String {
+        let mut line = format!("{}{}", self.prefix, self.name);
+        if self.is_dir && self.depth > 0 {
+            line.push('/');
+        }
+        if let Some(tokens) = self.tokens {
+            line.push_str(&format!(" ({} tokens)", tokens));
+        }
+        if let Some(change) = self.change {
+            line.push_str(&format!(" [{}]", change.name()));
+        }
+        line
+    }
+}
+
+/// An included file, as handed to `OutputWriter::write_file`.
+pub struct FileRecord<'a> {
+    pub path: &'a Path,
+    pub size: u64,
+    pub tokens: usize,
+    /// The file's contents, or `None` when only its diff is shown.
+    pub contents: Option<io::Result<String>>,
+    /// How the file changed, when only changed files are included.
+    pub change: Option<ChangeKind>,
+    /// Where a renamed file was before.
+    pub renamed_from: Option<PathBuf>,
+    /// Unified diff against the base revision, shown before the contents.
+    pub diff: Option<String>,
+    /// What the contents were converted from, when they are not the file's
+    /// bytes verbatim (e.g. `PNG image, base64`).
+    pub encoding: Option<String>,
+    /// Set when lines were cut from the middle or end of an oversized file.
+    pub truncation: Option<Truncation>,
+    /// Set when an oversized file was split across several blocks.
+    pub chunk: Option<Chunk>,
+}
+
+/// Position of a block within a file that was split on line boundaries.
+#[derive(Clone, Copy)]
+pub struct Chunk {
+    pub index: usize,
+    pub count: usize,
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+impl Chunk {
+    pub fn label(&self) -> String {
+        format!(
+            "part {} of {}, lines {}-{}",
+            self.index, self.count, self.first_line, self.last_line
+        )
+    }
+}
+
+impl FileRecord<'_> {
+    /// Notes shown next to the path in a file's heading.
+    pub fn label(&self) -> Option<String> {
+        let change = match (&self.renamed_from, self.change) {
+            (Some(old), _) => Some(format!("renamed from {}", old.display())),
+            (None, change) => change.map(|change| change.name().to_string()),
+        };
+        let notes: Vec<String> = change
+            .into_iter()
+            .chain(self.encoding.iter().cloned())
+            .chain(self.truncation.map(|t| {
+                format!("truncated, {} of {} lines omitted", t.omitted_lines, t.total_lines)
+            }))
+            .chain(self.chunk.as_ref().map(Chunk::label))
+            .collect();
+        (!notes.is_empty()).then(|| notes.join("; "))
+    }
+
+    pub fn extension(&self) -> Option<String> {
+        self.path.extension().map(|ext| ext.to_string_lossy().into_owned())
+    }
+
+    pub fn line_count(&self) -> usize {
+        match &self.contents {
+            Some(Ok(contents)) => contents.lines().count(),
+            _ => 0,
+        }
+    }
+}
+
+/// A code file left out of the contents section, listed in the trailer.
+pub struct Omitted<'a> {
+    pub path: &'a Path,
+    pub reason: String,
+}
+
+/// Renders the sections of the output in a particular format.
+///
+/// Sections are written in order: header, tree (unless disabled), contents
+/// heading, one call to `write_file` per included file, the trailer listing
+/// omitted files, then `finish`.
+pub trait OutputWriter {
+    fn write_header(&mut self, out: &mut dyn Write, summary: &Summary) -> io::Result<()>;
+
+    fn write_tree(&mut self, out: &mut dyn Write, tree: &[TreeEntry]) -> io::Result<()>;
+
+    fn begin_contents(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_file(&mut self, out: &mut dyn Write, file: &FileRecord) -> io::Result<()>;
+
+    fn write_omitted(&mut self, out: &mut dyn Write, omitted: &[Omitted]) -> io::Result<()>;
+
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(format: OutputFormat, part: Option<usize>) -> Vec<u8> {
+        let summary = Summary {
+            root: Path::new("/work/project"),
+            total_files: 12,
+            code_files: 7,
+            total_tokens: 3400,
+            tokenizer: "heuristic",
+            part,
+            repository: None,
+        };
+        let mut out = Vec::new();
+        format.writer().write_header(&mut out, &summary).unwrap();
+        out.truncate(MARKER_LEN);
+        out
+    }
+
+    #[test]
+    fn recognizes_headers_of_every_format() {
+        for format in [OutputFormat::Text, OutputFormat::Markdown, OutputFormat::Json, OutputFormat::Xml] {
+            assert!(is_generated(&header(format, None)));
+            assert!(is_generated(&header(format, Some(2))));
+        }
+
+        let record = FileRecord {
+            path: Path::new("src/main.rs"),
+            size: 12,
+            tokens: 3,
+            contents: Some(Ok("fn main() {}".to_string())),
+            change: None,
+            renamed_from: None,
+            diff: None,
+            encoding: None,
+            truncation: None,
+            chunk: None,
+        };
+        let mut jsonl = Vec::new();
+        OutputFormat::Jsonl.writer().write_file(&mut jsonl, &record).unwrap();
+        assert!(is_generated(&jsonl));
+    }
+
+    #[test]
+    fn ignores_files_that_only_share_the_title() {
+        assert!(!is_generated(b"Directory Tree and Code Contents\n\nprint('hello')\n"));
+        assert!(!is_generated(b"Directory Tree and Code Contents\nRoot Directory: here\n"));
+        assert!(!is_generated(b"# Directory Tree and Code Contents\n\nSome notes.\n"));
+        assert!(!is_generated(b"<code_tree root=\"x\">\n</code_tree>\n"));
+        assert!(!is_generated(b"{\"root\": \"x\"}"));
+    }
+}
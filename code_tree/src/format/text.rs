@@ -0,0 +1,70 @@
+use std::io::{self, Write};
+
+use super::{FileRecord, Omitted, OutputWriter, Summary, TreeEntry};
+
+/// The original plain-text layout.
+pub struct TextWriter;
+
+impl OutputWriter for TextWriter {
+    fn write_header(&mut self, out: &mut dyn Write, summary: &Summary) -> io::Result<()> {
+        writeln!(out, "Directory Tree and Code Contents\n")?;
+        if let Some(part) = summary.part {
+            writeln!(out, "Part: {}\n", part)?;
+        }
+        writeln!(out, "Root Directory: {}\n", summary.root.display())?;
+        writeln!(out, "Total Files: {}\n", summary.total_files)?;
+        writeln!(out, "Code Files: {}\n", summary.code_files)?;
+        writeln!(out, "Total Tokens: {} ({})\n", summary.total_tokens, summary.tokenizer)?;
+        if let Some(repo) = summary.repository {
+            writeln!(out, "Repository: {}\n", repo.name)?;
+            writeln!(out, "Commit: {}\n", repo.describe())?;
+            if !repo.recent_commits.is_empty() {
+                writeln!(out, "Recent Commits:")?;
+                for commit in &repo.recent_commits {
+                    writeln!(out, "{} {}", commit.id, commit.subject)?;
+                }
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_tree(&mut self, out: &mut dyn Write, tree: &[TreeEntry]) -> io::Result<()> {
+        for entry in tree {
+            writeln!(out, "{}", entry.line())?;
+        }
+        Ok(())
+    }
+
+    fn begin_contents(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "\nCode Contents:\n")
+    }
+
+    fn write_file(&mut self, out: &mut dyn Write, file: &FileRecord) -> io::Result<()> {
+        match file.label() {
+            Some(label) => writeln!(out, "\n=== File: {} ({}) ===\n", file.path.display(), label)?,
+            None => writeln!(out, "\n=== File: {} ===\n", file.path.display())?,
+        }
+
+        if let Some(diff) = &file.diff {
+            writeln!(out, "{}", diff)?;
+        }
+        match &file.contents {
+            Some(Ok(contents)) => writeln!(out, "{}", contents),
+            Some(Err(e)) => writeln!(out, "Error reading file: {}", e),
+            None => Ok(()),
+        }
+    }
+
+    fn write_omitted(&mut self, out: &mut dyn Write, omitted: &[Omitted]) -> io::Result<()> {
+        if omitted.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "\nOmitted Files:\n")?;
+        for file in omitted {
+            writeln!(out, "{} ({})", file.path.display(), file.reason)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{self, Write};
+
+use super::{FileRecord, Omitted, OutputWriter, Summary, TreeEntry};
+use crate::language::language_for;
+
+/// XML-tagged layout for pasting into prompts: one `<document>` per file with
+/// its contents wrapped in CDATA.
+pub struct XmlWriter;
+
+impl OutputWriter for XmlWriter {
+    fn write_header(&mut self, out: &mut dyn Write, summary: &Summary) -> io::Result<()> {
+        write!(
+            out,
+            "<code_tree root=\"{}\" total_files=\"{}\" code_files=\"{}\" total_tokens=\"{}\" tokenizer=\"{}\"",
+            escape(&summary.root.to_string_lossy()),
+            summary.total_files,
+            summary.code_files,
+            summary.total_tokens,
+            summary.tokenizer,
+        )?;
+        if let Some(part) = summary.part {
+            write!(out, " part=\"{}\"", part)?;
+        }
+        writeln!(out, ">")?;
+
+        if let Some(repo) = summary.repository {
+            write!(out, "<repository name=\"{}\"", escape(&repo.name))?;
+            if let Some(commit) = &repo.commit {
+                write!(out, " commit=\"{}\"", commit)?;
+            }
+            if let Some(branch) = &repo.branch {
+                write!(out, " branch=\"{}\"", escape(branch))?;
+            }
+            writeln!(out, " dirty=\"{}\">", repo.dirty)?;
+            for commit in &repo.recent_commits {
+                writeln!(out, "<commit id=\"{}\">{}</commit>", commit.id, escape(&commit.subject))?;
+            }
+            writeln!(out, "</repository>")?;
+        }
+        Ok(())
+    }
+
+    fn write_tree(&mut self, out: &mut dyn Write, tree: &[TreeEntry]) -> io::Result<()> {
+        writeln!(out, "<directory_tree>")?;
+        for entry in tree {
+            writeln!(out, "{}", escape(&entry.line()))?;
+        }
+        writeln!(out, "</directory_tree>")
+    }
+
+    fn begin_contents(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "<documents>")
+    }
+
+    fn write_file(&mut self, out: &mut dyn Write, file: &FileRecord) -> io::Result<()> {
+        write!(
+            out,
+            "<document path=\"{}\" size=\"{}\" tokens=\"{}\"",
+            escape(&file.path.to_string_lossy()),
+            file.size,
+            file.tokens,
+        )?;
+        if let Some(language) = language_for(file.path) {
+            write!(out, " language=\"{}\"", language)?;
+        }
+        if let Some(encoding) = &file.encoding {
+            write!(out, " encoding=\"{}\"", escape(encoding))?;
+        }
+        if let Some(truncation) = &file.truncation {
+            write!(
+                out,
+                " truncated_lines=\"{}\" total_lines=\"{}\"",
+                truncation.omitted_lines, truncation.total_lines
+            )?;
+        }
+        if let Some(chunk) = &file.chunk {
+            write!(
+                out,
+                " chunk=\"{}/{}\" lines=\"{}-{}\"",
+                chunk.index, chunk.count, chunk.first_line, chunk.last_line
+            )?;
+        }
+        if let Some(change) = file.change {
+            write!(out, " change=\"{}\"", change.name())?;
+        }
+        if let Some(old) = &file.renamed_from {
+            write!(out, " renamed_from=\"{}\"", escape(&old.to_string_lossy()))?;
+        }
+        writeln!(out, ">")?;
+
+        if let Some(diff) = &file.diff {
+            write!(out, "<diff><![CDATA[")?;
+            write!(out, "{}", cdata(diff))?;
+            writeln!(out, "]]></diff>")?;
+        }
+        match &file.contents {
+            Some(Ok(contents)) => {
+                write!(out, "<content><![CDATA[")?;
+                write!(out, "{}", cdata(contents))?;
+                writeln!(out, "]]></content>")?;
+            }
+            Some(Err(e)) => writeln!(out, "<error>{}</error>", escape(&e.to_string()))?,
+            None => {}
+        }
+        writeln!(out, "</document>")
+    }
+
+    fn write_omitted(&mut self, out: &mut dyn Write, omitted: &[Omitted]) -> io::Result<()> {
+        writeln!(out, "</documents>")?;
+        if omitted.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "<omitted_files>")?;
+        for file in omitted {
+            writeln!(
+                out,
+                "<file path=\"{}\" reason=\"{}\"/>",
+                escape(&file.path.to_string_lossy()),
+                escape(&file.reason),
+            )?;
+        }
+        writeln!(out, "</omitted_files>")
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "</code_tree>")
+    }
+}
+
+/// Escapes text for use in element content or a double-quoted attribute.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Splits any `]]>` in `contents` across two CDATA sections so it cannot
+/// terminate the section early.
+fn cdata(contents: &str) -> String {
+    contents.replace("]]>", "]]]]><![CDATA[>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_cdata_terminators() {
+        assert_eq!(cdata("a]]>b"), "a]]]]><![CDATA[>b");
+        assert_eq!(cdata("plain ]] >"), "plain ]] >");
+    }
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(escape(r#"<a href="x">'&'"#), "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;");
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
+use git2::{Delta, Diff, DiffOptions, IndexEntryExtendedFlag, Patch, Repository, StatusOptions, Tree};
+
+/// Which changes select the files whose contents are included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeSet {
+    /// Differences between a revision and the working tree, like `git diff <rev>`
+    Since(String),
+    /// Staged changes, like `git diff --cached`
+    Staged,
+    /// Staged and unstaged changes plus untracked files, like `git status`
+    Uncommitted,
+}
+
+/// How a file changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    TypeChanged,
+}
+
+impl ChangeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::TypeChanged => "type changed",
+        }
+    }
+}
+
+/// How file blocks show a change when diffs are embedded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiffMode {
+    /// Unchanged lines shown around each hunk.
+    pub context_lines: u32,
+    /// Follow the diff with the full new version of the file.
+    pub full_contents: bool,
+}
+
+/// A changed file.
+#[derive(Clone, Debug)]
+pub struct FileChange {
+    pub kind: ChangeKind,
+    /// Absolute path the file had before it was renamed.
+    pub old_path: Option<PathBuf>,
+    /// Unified diff of the file, when diffs were asked for.
+    pub patch: Option<String>,
+}
+
+/// The files changed in the repository containing a directory, keyed by
+/// absolute path. Deleted files are keyed by the path they had.
+pub struct Changes {
+    files: HashMap<PathBuf, FileChange>,
+}
+
+impl Changes {
+    /// Reads the changes in `set` from the repository that contains `root`,
+    /// with a unified diff of each file when `diff` is set.
+    pub fn load(root: &Path, set: &ChangeSet, diff: Option<DiffMode>) -> io::Result<Self> {
+        let repo = Repository::discover(root).map_err(git_error)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "git: repository has no working tree"))?
+            .canonicalize()?;
+
+        let changes = diff_of(&repo, set, diff).map_err(git_error)?;
+        let mut files = HashMap::new();
+        for (index, delta) in changes.deltas().enumerate() {
+            let kind = match delta.status() {
+                Delta::Added | Delta::Untracked | Delta::Copied => ChangeKind::Added,
+                Delta::Modified => ChangeKind::Modified,
+                Delta::Deleted => ChangeKind::Deleted,
+                Delta::Renamed => ChangeKind::Renamed,
+                Delta::Typechange => ChangeKind::TypeChanged,
+                _ => continue,
+            };
+            let file = match kind {
+                ChangeKind::Deleted => delta.old_file(),
+                _ => delta.new_file(),
+            };
+            let Some(path) = file.path() else {
+                continue;
+            };
+            let old_path = match kind {
+                ChangeKind::Renamed => delta.old_file().path().map(|old| workdir.join(old)),
+                _ => None,
+            };
+            let patch = match diff {
+                Some(_) => patch_text(&changes, index).map_err(git_error)?,
+                None => None,
+            };
+            files.insert(workdir.join(path), FileChange { kind, old_path, patch });
+        }
+        Ok(Changes { files })
+    }
+
+    /// How the file at the absolute `path` changed, if it did.
+    pub fn get(&self, path: &Path) -> Option<&FileChange> {
+        self.files.get(path)
+    }
+
+    /// The deleted files, by the absolute path they had.
+    pub fn deleted(&self) -> impl Iterator<Item = (&Path, &FileChange)> {
+        self.files
+            .iter()
+            .filter(|(_, change)| change.kind == ChangeKind::Deleted)
+            .map(|(path, change)| (path.as_path(), change))
+    }
+}
+
+/// Mode of an index entry that records a submodule's commit.
+const GITLINK_MODE: u32 = 0o160000;
+
+/// The files in the git index of the repository containing a directory, by
+/// path relative to that directory.
+///
+/// Entries excluded by a sparse checkout are left out, and so are submodules:
+/// their files belong to another repository's index.
+#[derive(Clone, Debug)]
+pub struct Tracked {
+    root: PathBuf,
+    files: HashSet<PathBuf>,
+    /// Every directory holding a tracked file, at any depth.
+    dirs: HashSet<PathBuf>,
+}
+
+impl Tracked {
+    /// Reads the index of the repository that contains `root`.
+    pub fn load(root: &Path) -> io::Result<Self> {
+        let repo = Repository::discover(root).map_err(git_error)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "git: repository has no working tree"))?
+            .canonicalize()?;
+        let canonical_root = root.canonicalize()?;
+        let index = repo.index().map_err(git_error)?;
+
+        let mut files = HashSet::new();
+        let mut dirs = HashSet::new();
+        for entry in index.iter() {
+            let sparse = IndexEntryExtendedFlag::from_bits_truncate(entry.flags_extended).is_skip_worktree();
+            if sparse || entry.mode & 0o170000 == GITLINK_MODE {
+                continue;
+            }
+            let path = workdir.join(String::from_utf8_lossy(&entry.path).as_ref());
+            let Ok(relative) = path.strip_prefix(&canonical_root) else {
+                continue;
+            };
+            dirs.extend(relative.ancestors().skip(1).map(Path::to_path_buf));
+            files.insert(relative.to_path_buf());
+        }
+        Ok(Tracked {
+            root: root.to_path_buf(),
+            files,
+            dirs,
+        })
+    }
+
+    /// Whether `path`, found by walking the root, is tracked or is a
+    /// directory with tracked files below it.
+    pub fn contains(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if is_dir {
+            self.dirs.contains(relative)
+        } else {
+            self.files.contains(relative)
+        }
+    }
+}
+
+/// Where a dump came from: the state of the repository containing the root.
+#[derive(Clone, Debug)]
+pub struct RepoInfo {
+    /// Name of the working tree's directory.
+    pub name: String,
+    /// Full hash of `HEAD`, or `None` before the first commit.
+    pub commit: Option<String>,
+    /// Checked-out branch, or `None` when `HEAD` is detached.
+    pub branch: Option<String>,
+    /// Whether tracked files have staged or unstaged changes. Untracked files
+    /// do not count, as with `git describe --dirty`.
+    pub dirty: bool,
+    /// The latest commits reachable from `HEAD`, newest first.
+    pub recent_commits: Vec<CommitSummary>,
+}
+
+/// A commit in [`RepoInfo::recent_commits`].
+#[derive(Clone, Debug)]
+pub struct CommitSummary {
+    /// Abbreviated hash.
+    pub id: String,
+    /// First line of the message.
+    pub subject: String,
+}
+
+impl RepoInfo {
+    /// Reads the repository that contains `root`, listing up to `log` recent commits.
+    pub fn load(root: &Path, log: usize) -> io::Result<Self> {
+        let repo = Repository::discover(root).map_err(git_error)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "git: repository has no working tree"))?
+            .canonicalize()?;
+        let name = workdir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+        let head = match repo.head() {
+            Ok(head) => Some(head),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(git_error(e)),
+        };
+        let commit = match &head {
+            Some(head) => Some(head.peel_to_commit().map_err(git_error)?),
+            None => None,
+        };
+        let branch = match &head {
+            Some(head) if head.is_branch() => head.shorthand().map(str::to_string),
+            Some(_) => None,
+            // An unborn branch still has a name, only no commits
+            None => repo.find_reference("HEAD").ok().and_then(|head| {
+                head.symbolic_target()
+                    .map(|target| target.trim_start_matches("refs/heads/").to_string())
+            }),
+        };
+
+        let mut options = StatusOptions::new();
+        options.include_untracked(false).include_ignored(false);
+        let dirty = !repo.statuses(Some(&mut options)).map_err(git_error)?.is_empty();
+
+        let mut recent_commits = Vec::new();
+        if let (Some(commit), true) = (&commit, log > 0) {
+            let mut walk = repo.revwalk().map_err(git_error)?;
+            walk.push(commit.id()).map_err(git_error)?;
+            for id in walk.take(log) {
+                let commit = repo.find_commit(id.map_err(git_error)?).map_err(git_error)?;
+                let id = commit.as_object().short_id().map_err(git_error)?;
+                recent_commits.push(CommitSummary {
+                    id: String::from_utf8_lossy(&id).into_owned(),
+                    subject: commit.summary().unwrap_or_default().to_string(),
+                });
+            }
+        }
+
+        Ok(RepoInfo {
+            name,
+            commit: commit.map(|commit| commit.id().to_string()),
+            branch,
+            dirty,
+            recent_commits,
+        })
+    }
+
+    /// The commit, branch and state on one line, e.g. `<hash> (main, dirty)`.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} ({}, {})",
+            self.commit.as_deref().unwrap_or("no commits yet"),
+            self.branch.as_deref().unwrap_or("detached HEAD"),
+            if self.dirty { "dirty" } else { "clean" },
+        )
+    }
+}
+
+fn diff_of<'r>(repo: &'r Repository, set: &ChangeSet, mode: Option<DiffMode>) -> Result<Diff<'r>, git2::Error> {
+    let mut options = DiffOptions::new();
+    if let Some(mode) = mode {
+        options.context_lines(mode.context_lines);
+    }
+    let mut diff = match set {
+        ChangeSet::Since(rev) => {
+            let tree = repo.revparse_single(rev)?.peel_to_tree()?;
+            repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))?
+        }
+        ChangeSet::Staged => repo.diff_tree_to_index(head_tree(repo)?.as_ref(), None, Some(&mut options))?,
+        ChangeSet::Uncommitted => {
+            options.include_untracked(true).recurse_untracked_dirs(true);
+            repo.diff_tree_to_workdir_with_index(head_tree(repo)?.as_ref(), Some(&mut options))?
+        }
+    };
+    diff.find_similar(None)?;
+    Ok(diff)
+}
+
+/// The unified diff of the `index`th file in `diff`, headers included.
+fn patch_text(diff: &Diff, index: usize) -> Result<Option<String>, git2::Error> {
+    let Some(mut patch) = Patch::from_diff(diff, index)? else {
+        return Ok(None);
+    };
+    let buf = patch.to_buf()?;
+    Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+}
+
+/// The tree of `HEAD`, or `None` before the first commit.
+fn head_tree(repo: &Repository) -> Result<Option<Tree<'_>>, git2::Error> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_tree()?)),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn git_error(e: git2::Error) -> io::Error {
+    io::Error::other(format!("git: {}", e.message()))
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+
+/// Infers a language tag (as used for fenced code blocks) from a file's name or extension.
+pub fn language_for(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_string_lossy();
+
+    let by_name = match file_name.as_ref() {
+        "Dockerfile" => Some("dockerfile"),
+        "Makefile" | "makefile" | "GNUmakefile" => Some("makefile"),
+        "CMakeLists.txt" => Some("cmake"),
+        "Cargo.lock" => Some("toml"),
+        _ => None,
+    };
+    if by_name.is_some() {
+        return by_name;
+    }
+
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "py" | "pyi" => "python",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "cshtml" | "razor" => "razor",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "go" => "go",
+        "rb" => "ruby",
+        "php" => "php",
+        "swift" => "swift",
+        "scala" => "scala",
+        "sh" | "bash" | "zsh" => "bash",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "json" => "json",
+        "xml" | "csproj" | "vbproj" | "fsproj" | "props" | "targets" | "config" | "xaml" => "xml",
+        "yml" | "yaml" => "yaml",
+        "toml" => "toml",
+        "ini" => "ini",
+        "md" | "markdown" => "markdown",
+        "sln" => "text",
+        "lua" => "lua",
+        "dart" => "dart",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "proto" => "protobuf",
+        "gradle" => "groovy",
+        _ => return None,
+    };
+    Some(language)
+}
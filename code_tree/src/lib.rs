@@ -0,0 +1,36 @@
+//! Generates a directory tree and concatenates the code files under a root
+//! directory into a single document, for sharing a codebase with LLMs.
+//!
+//! The `code_tree` binary is a thin wrapper around this crate. To produce the
+//! same output from your own tools:
+//!
+//! ```no_run
+//! use code_tree::{OutputFormat, ScanOptions};
+//!
+//! let options = ScanOptions::builder("src")
+//!     .format(OutputFormat::Markdown)
+//!     .output_file("context.md")
+//!     .exclude("generated/")
+//!     .build()?;
+//! let report = code_tree::generate(&options, |_, _| {})?;
+//! println!("{} of {} files included", report.code_files, report.total_files);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`files`] lists what a scan would see without writing anything, and a
+//! custom [`OutputWriter`] can be plugged in with
+//! [`ScanOptionsBuilder::writer`].
+
+pub mod budget;
+pub mod content;
+pub mod format;
+pub mod git;
+pub mod language;
+pub mod output;
+pub mod scan;
+pub mod secrets;
+pub mod tokens;
+pub mod tree;
+
+pub use format::{FileRecord, Omitted, OutputFormat, OutputWriter, Summary, TreeEntry, WriterFactory};
+pub use scan::{files, generate, FileEntry, Files, Report, ScanOptions, ScanOptionsBuilder};
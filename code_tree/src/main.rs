@@ -1,177 +1,555 @@
+mod config_file;
+
 use std::{
-    fs::{self, File},
-    io::{self, Write},
+    fs,
+    io::{self, IsTerminal},
     path::PathBuf,
 };
-use walkdir::WalkDir;
-use clap::{Parser, ArgAction};
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
 use indicatif::{ProgressBar, ProgressStyle};
+use code_tree::{
+    budget::Priority,
+    content::{BinaryEncoding, LoadOptions, Oversized, SizeLimit},
+    git::{ChangeSet, DiffMode},
+    output::{SplitLimit, STDOUT},
+    scan::{DEFAULT_EXTENSIONS, DEFAULT_IGNORED_DIRS},
+    secrets::Redactor,
+    tokens::{TokenStats, Tokenizer, TokenizerKind},
+    tree::{SortOrder, TreeCharset},
+    OutputFormat, ScanOptions,
+};
+use config_file::{from_command_line, parse_enum, Settings, SizeValue};
+
+const SELECTION_HELP: &str = "\
+File selection precedence:
+  1. --ignored-dirs, .gitignore rules and --exclude globs remove paths from the scan entirely.
+  2. If any --include glob is given, only files matching one of them have their contents included.
+  3. Otherwise, files whose extension is listed in --extensions have their contents included.
+
+Globs use .gitignore syntax and are matched relative to ROOT_PATH: `*.rs` matches at any depth,
+`src/**/*.rs` and `/Makefile` are anchored to the root, and a trailing `/` matches directories only.
+
+Configuration files, later ones overriding earlier ones:
+  1. The user config, e.g. ~/.config/code_tree/config.toml.
+  2. The nearest code_tree.toml or .code_tree.toml in ROOT_PATH or one of its parents (or --config).
+  3. The [profile.NAME] table selected with --profile.
+Options given on the command line override all of them.";
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = SELECTION_HELP)]
 struct Cli {
     /// Root directory to analyze
     #[arg(default_value = ".")]
     root_path: PathBuf,
 
-    /// Output file path
+    /// Output file path, or `-` for stdout (the default when stdout is not a terminal)
     #[arg(short, long, default_value = "code_output.txt")]
     output: PathBuf,
 
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Leave the directory tree section out of the output
+    #[arg(long, action = ArgAction::SetTrue)]
+    no_tree: bool,
+
+    /// Characters used to draw the directory tree
+    #[arg(long, value_enum, default_value_t = TreeCharset::Unicode)]
+    tree_charset: TreeCharset,
+
+    /// Add the git commit, branch, dirty state and recent commits to the header
+    #[arg(long, action = ArgAction::SetTrue)]
+    git_info: bool,
+
+    /// Number of recent commit subjects listed by --git-info
+    #[arg(long, value_name = "N", default_value_t = 5, requires = "git_info")]
+    git_log: usize,
+
+    /// Order of the tree and of the files in the contents section
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = SortOrder::DirsFirst)]
+    sort: SortOrder,
+
+    /// Include only files changed since this git revision (branch, tag or commit)
+    #[arg(long, value_name = "REF", group = "git_changes")]
+    changed_since: Option<String>,
+
+    /// Include only files with staged changes
+    #[arg(long, action = ArgAction::SetTrue, group = "git_changes")]
+    staged: bool,
+
+    /// Include only files with staged or unstaged changes, and untracked files
+    #[arg(long, action = ArgAction::SetTrue, group = "git_changes")]
+    uncommitted: bool,
+
+    /// Show the unified diff of each file changed since this git revision instead of its contents
+    #[arg(long, value_name = "REF", group = "git_changes")]
+    diff: Option<String>,
+
+    /// Unchanged lines shown around each change in --diff mode
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "diff")]
+    diff_context: u32,
+
+    /// In --diff mode, follow each diff with the full new version of the file
+    #[arg(long, action = ArgAction::SetTrue, requires = "diff")]
+    diff_full: bool,
+
+    /// With --changed-since/--staged/--uncommitted/--diff, show every file in the tree, marking the changed ones
+    #[arg(long, action = ArgAction::SetTrue)]
+    full_tree: bool,
+
     /// Directories to ignore during scanning
-    #[arg(short, long, default_value_t = String::from(".git,node_modules,target,.idea,venv,bin,obj,Debug,Release"))]
+    #[arg(short, long, default_value = DEFAULT_IGNORED_DIRS)]
     ignored_dirs: String,
 
     /// File extensions to include
-    #[arg(short, long, default_value_t = String::from("rs,js,py,cpp,c,java,go,ts,cs,csproj,sln,cshtml,razor,json,xml,config,yml,yaml"))]
+    #[arg(short, long, default_value = DEFAULT_EXTENSIONS)]
     extensions: String,
 
+    /// Include only files matching this glob instead of using --extensions (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Exclude files and directories matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Stop including files once their contents reach this many tokens
+    #[arg(long, value_name = "N")]
+    max_tokens: Option<usize>,
+
+    /// Stop including files once their contents reach this many bytes
+    #[arg(long, value_name = "N")]
+    max_bytes: Option<u64>,
+
+    /// Order in which files claim the --max-tokens/--max-bytes budget
+    #[arg(long, value_enum, default_value_t = Priority::Default)]
+    priority: Priority,
+
+    /// Give files matching this glob priority for the budget; earlier globs win (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    priority_glob: Vec<String>,
+
+    /// Roll over to a new `name.partN.ext` file before a part exceeds this many tokens
+    #[arg(long, value_name = "N", conflicts_with = "split_bytes")]
+    split_tokens: Option<usize>,
+
+    /// Roll over to a new `name.partN.ext` file before a part exceeds this many bytes
+    #[arg(long, value_name = "N")]
+    split_bytes: Option<usize>,
+
+    /// Emit binary files (hex or base64) instead of skipping them
+    #[arg(long, value_enum, value_name = "ENCODING", num_args = 0..=1, require_equals = true, default_missing_value = "base64")]
+    include_binary: Option<BinaryEncoding>,
+
+    /// Largest file emitted in full, in bytes (`200KB`, `1MB`) or lines (`2000lines`)
+    #[arg(long, value_name = "SIZE")]
+    max_file_size: Option<SizeLimit>,
+
+    /// What to do with files over --max-file-size
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = Oversized::Head)]
+    oversized: Oversized,
+
+    /// Convert CRLF line endings to LF in emitted files
+    #[arg(long, action = ArgAction::SetTrue)]
+    normalize_newlines: bool,
+
+    /// Prefix each emitted line with its line number
+    #[arg(long, action = ArgAction::SetTrue)]
+    line_numbers: bool,
+
+    /// Text between a line number and the line
+    #[arg(long, value_name = "SEP", default_value = " | ")]
+    line_number_separator: String,
+
+    /// Replace secrets (private keys, AWS keys, JWTs, password assignments, high-entropy strings) with placeholders
+    #[arg(long, action = ArgAction::SetTrue)]
+    redact_secrets: bool,
+
+    /// Like --redact-secrets, but exit with an error after writing the output if any secret was found
+    #[arg(long, action = ArgAction::SetTrue)]
+    fail_on_secrets: bool,
+
+    /// Include only files tracked in the git index, skipping submodules and paths outside a sparse checkout
+    #[arg(long, action = ArgAction::SetTrue)]
+    tracked_only: bool,
+
+    /// Do not honor .gitignore, .ignore, .git/info/exclude or global git excludes
+    #[arg(long, action = ArgAction::SetTrue)]
+    no_gitignore: bool,
+
+    /// How to count tokens
+    #[arg(long, value_enum, default_value_t = TokenizerKind::Heuristic)]
+    tokenizer: TokenizerKind,
+
+    /// Local `.tiktoken` vocabulary file for the cl100k/o200k tokenizers
+    #[arg(long, value_name = "PATH")]
+    tokenizer_file: Option<PathBuf>,
+
+    /// Threads used to read and tokenize files (default: one per CPU)
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Print token counts per directory and for the largest files after the run
+    #[arg(long, action = ArgAction::SetTrue)]
+    stats: bool,
+
     /// Verbose output
     #[arg(short, long, action = ArgAction::SetTrue)]
     verbose: bool,
-}
 
-struct Config {
-    root_path: PathBuf,
-    output_file: PathBuf,
-    ignored_dirs: Vec<String>,
-    allowed_extensions: Vec<String>,
-    verbose: bool,
-}
+    /// Read settings from this file instead of the nearest code_tree.toml
+    #[arg(long, value_name = "PATH", conflicts_with = "no_config")]
+    config: Option<PathBuf>,
 
-impl Config {
-    fn new(cli: Cli) -> Self {
-        Config {
-            root_path: cli.root_path,
-            output_file: cli.output,
-            ignored_dirs: cli.ignored_dirs.split(',').map(|s| s.to_string()).collect(),
-            allowed_extensions: cli.extensions.split(',').map(|s| s.to_string()).collect(),
-            verbose: cli.verbose,
-        }
-    }
+    /// Ignore the user and project configuration files
+    #[arg(long, action = ArgAction::SetTrue)]
+    no_config: bool,
+
+    /// Apply the settings of a `[profile.NAME]` table from the configuration files
+    #[arg(long, value_name = "NAME", conflicts_with = "no_config")]
+    profile: Option<String>,
 }
 
-fn count_total_files(config: &Config) -> io::Result<(usize, usize)> {
-    let mut total_files = 0;
-    let mut code_files = 0;
-
-    for entry in WalkDir::new(&config.root_path)
-        .into_iter()
-        .filter_entry(|e| !is_ignored(e, &config.ignored_dirs))
-    {
-        let entry = entry?;
-        if entry.file_type().is_file() {
-            total_files += 1;
-            
-            if let Some(extension) = entry.path().extension() {
-                if config.allowed_extensions.contains(&extension.to_string_lossy().to_string()) {
-                    code_files += 1;
-                }
+impl Cli {
+    /// Fills in every option not given on the command line from `settings`.
+    fn apply(&mut self, matches: &ArgMatches, settings: &Settings) -> io::Result<()> {
+        let unset = |id: &str| !from_command_line(matches, id);
+
+        if let (Some(output), true) = (&settings.output, unset("output")) {
+            self.output = output.clone();
+        }
+        if let (Some(format), true) = (&settings.format, unset("format")) {
+            self.format = parse_enum("format", format)?;
+        }
+        if let (Some(tree), true) = (settings.tree, unset("no_tree")) {
+            self.no_tree = !tree;
+        }
+        if let (Some(charset), true) = (&settings.tree_charset, unset("tree_charset")) {
+            self.tree_charset = parse_enum("tree-charset", charset)?;
+        }
+        if let (Some(git_info), true) = (settings.git_info, unset("git_info")) {
+            self.git_info = git_info;
+        }
+        if let (Some(log), true) = (settings.git_log, unset("git_log")) {
+            self.git_log = log;
+        }
+        if let (Some(sort), true) = (&settings.sort, unset("sort")) {
+            self.sort = parse_enum("sort", sort)?;
+        }
+        if let (Some(context), true) = (settings.diff_context, unset("diff_context")) {
+            self.diff_context = context;
+        }
+        if let (Some(full), true) = (settings.diff_full, unset("diff_full")) {
+            self.diff_full = full;
+        }
+        if let (Some(full_tree), true) = (settings.full_tree, unset("full_tree")) {
+            self.full_tree = full_tree;
+        }
+        if let (Some(dirs), true) = (&settings.ignored_dirs, unset("ignored_dirs")) {
+            self.ignored_dirs = dirs.joined();
+        }
+        if let (Some(extensions), true) = (&settings.extensions, unset("extensions")) {
+            self.extensions = extensions.joined();
+        }
+        if let (Some(include), true) = (&settings.include, unset("include")) {
+            self.include = include.items();
+        }
+        if let (Some(exclude), true) = (&settings.exclude, unset("exclude")) {
+            self.exclude = exclude.items();
+        }
+        if unset("max_tokens") {
+            self.max_tokens = settings.max_tokens;
+        }
+        if unset("max_bytes") {
+            self.max_bytes = settings.max_bytes;
+        }
+        if let (Some(priority), true) = (&settings.priority, unset("priority")) {
+            self.priority = parse_enum("priority", priority)?;
+        }
+        if let (Some(globs), true) = (&settings.priority_glob, unset("priority_glob")) {
+            self.priority_glob = globs.items();
+        }
+        if unset("split_tokens") && unset("split_bytes") {
+            if settings.split_tokens.is_some() && settings.split_bytes.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "split-tokens and split-bytes cannot both be set in config files",
+                ));
             }
+            self.split_tokens = settings.split_tokens;
+            self.split_bytes = settings.split_bytes;
+        }
+        if let (Some(encoding), true) = (&settings.include_binary, unset("include_binary")) {
+            self.include_binary = match encoding.as_str() {
+                "none" => None,
+                encoding => Some(parse_enum("include-binary", encoding)?),
+            };
+        }
+        if let (Some(size), true) = (&settings.max_file_size, unset("max_file_size")) {
+            self.max_file_size = Some(match size {
+                SizeValue::Bytes(bytes) => SizeLimit::Bytes(*bytes),
+                SizeValue::Text(text) => text.parse().map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid `max-file-size` in config file: {}", e))
+                })?,
+            });
+        }
+        if let (Some(oversized), true) = (&settings.oversized, unset("oversized")) {
+            self.oversized = parse_enum("oversized", oversized)?;
+        }
+        if let (Some(normalize), true) = (settings.normalize_newlines, unset("normalize_newlines")) {
+            self.normalize_newlines = normalize;
+        }
+        if let (Some(line_numbers), true) = (settings.line_numbers, unset("line_numbers")) {
+            self.line_numbers = line_numbers;
+        }
+        if let (Some(separator), true) = (&settings.line_number_separator, unset("line_number_separator")) {
+            self.line_number_separator = separator.clone();
+        }
+        if let (Some(redact), true) = (settings.redact_secrets, unset("redact_secrets")) {
+            self.redact_secrets = redact;
+        }
+        if let (Some(fail), true) = (settings.fail_on_secrets, unset("fail_on_secrets")) {
+            self.fail_on_secrets = fail;
         }
+        if let (Some(gitignore), true) = (settings.gitignore, unset("no_gitignore")) {
+            self.no_gitignore = !gitignore;
+        }
+        if let (Some(tracked_only), true) = (settings.tracked_only, unset("tracked_only")) {
+            self.tracked_only = tracked_only;
+        }
+        if let (Some(tokenizer), true) = (&settings.tokenizer, unset("tokenizer")) {
+            self.tokenizer = parse_enum("tokenizer", tokenizer)?;
+        }
+        if unset("tokenizer_file") {
+            self.tokenizer_file = settings.tokenizer_file.clone();
+        }
+        if unset("jobs") {
+            self.jobs = settings.jobs;
+        }
+        if let (Some(stats), true) = (settings.stats, unset("stats")) {
+            self.stats = stats;
+        }
+        if let (Some(verbose), true) = (settings.verbose, unset("verbose")) {
+            self.verbose = verbose;
+        }
+        Ok(())
     }
+}
 
-    Ok((total_files, code_files))
+/// Prints a status message to stdout, or to stderr when stdout carries the output.
+macro_rules! status {
+    ($options:expr, $($arg:tt)*) => {
+        if $options.writes_to_stdout() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
 }
 
-fn process_directory(config: &Config) -> io::Result<()> {
-    // Count total files first
-    let (total_files, code_files) = count_total_files(config)?;
+/// Turns the parsed command line into scan options.
+fn scan_options(cli: Cli) -> io::Result<ScanOptions> {
+    let tokenizer = Tokenizer::load(cli.tokenizer, cli.tokenizer_file.as_deref())?;
+    let diff = cli.diff.is_some().then_some(DiffMode {
+        context_lines: cli.diff_context,
+        full_contents: cli.diff_full,
+    });
+    let changes = match (cli.changed_since.or(cli.diff), cli.staged, cli.uncommitted) {
+        (Some(rev), _, _) => Some(ChangeSet::Since(rev)),
+        (None, true, _) => Some(ChangeSet::Staged),
+        (None, false, true) => Some(ChangeSet::Uncommitted),
+        (None, false, false) => None,
+    };
 
+    let mut builder = ScanOptions::builder(cli.root_path)
+        .output_file(cli.output)
+        .format(cli.format)
+        .show_tree(!cli.no_tree)
+        .tree_charset(cli.tree_charset)
+        .git_info(cli.git_info.then_some(cli.git_log))
+        .sort(cli.sort)
+        .changes(changes)
+        .diff(diff)
+        .full_tree(cli.full_tree)
+        .ignored_dirs(cli.ignored_dirs.split(','))
+        .extensions(cli.extensions.split(','))
+        .max_tokens(cli.max_tokens)
+        .max_bytes(cli.max_bytes)
+        .priority(cli.priority)
+        .split(
+            cli.split_tokens
+                .map(SplitLimit::Tokens)
+                .or(cli.split_bytes.map(SplitLimit::Bytes)),
+        )
+        .load_options(LoadOptions {
+            include_binary: cli.include_binary,
+            normalize_newlines: cli.normalize_newlines,
+            line_numbers: cli.line_numbers.then_some(cli.line_number_separator),
+            redact: (cli.redact_secrets || cli.fail_on_secrets).then(Redactor::new),
+            max_file_size: cli.max_file_size,
+            oversized: cli.oversized,
+        })
+        .respect_gitignore(!cli.no_gitignore)
+        .tracked_only(cli.tracked_only)
+        .tokenizer(tokenizer)
+        .jobs(cli.jobs.unwrap_or(0))
+        .verbose(cli.verbose);
+    for glob in cli.include {
+        builder = builder.include(glob);
+    }
+    for glob in cli.exclude {
+        builder = builder.exclude(glob);
+    }
+    for glob in cli.priority_glob {
+        builder = builder.priority_glob(glob);
+    }
+    builder.build()
+}
+
+fn process_directory(options: &ScanOptions, print_stats_after: bool, fail_on_secrets: bool) -> io::Result<()> {
     // Create progress bar
-    let pb = ProgressBar::new(total_files as u64);
+    let pb = ProgressBar::new(0);
     pb.set_style(ProgressStyle::default_bar()
         .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({eta})")
         .unwrap()
         .progress_chars("#>-"));
 
-    // Create output file
-    let mut output = File::create(&config.output_file)?;
+    // Files finish out of order on several threads, so count them here.
+    let report = code_tree::generate(options, |_, total| {
+        pb.set_length(total as u64);
+        pb.inc(1);
+    })?;
+
+    pb.finish_with_message("Scan complete");
+
+    if options.verbose {
+        status!(options, "Total files: {}, Code files: {}", report.total_files, report.code_files);
+    }
     
-    if config.verbose {
-        println!("Total files: {}, Code files: {}", total_files, code_files);
-    }
-
-    writeln!(output, "Directory Tree and Code Contents\n")?;
-    writeln!(output, "Root Directory: {}\n", config.root_path.display())?;
-    writeln!(output, "Total Files: {}\n", total_files)?;
-    writeln!(output, "Code Files: {}\n", code_files)?;
-
-    // Generate directory tree
-    for entry in WalkDir::new(&config.root_path)
-        .into_iter()
-        .filter_entry(|e| !is_ignored(e, &config.ignored_dirs))
-    {
-        let entry = entry?;
-        let path = entry.path();
-        
-        let depth = entry.depth();
-        let prefix = "    ".repeat(depth);
-        
-        let name = path.file_name()
-            .unwrap_or_default()
-            .to_string_lossy();
-            
-        writeln!(output, "{}{}{}", prefix, "├── ", name)?;
-    }
-
-    writeln!(output, "\nCode Contents:\n")?;
-
-    // Process code files
-    for entry in WalkDir::new(&config.root_path)
-        .into_iter()
-        .filter_entry(|e| !is_ignored(e, &config.ignored_dirs))
-    {
-        let entry = entry?;
-        if !entry.file_type().is_file() {
-            continue;
+    if !options.writes_to_stdout() {
+        for part in &report.parts {
+            println!("Successfully generated code output at: {}", part.display());
         }
+    }
 
-        pb.inc(1);
+    if !report.truncated.is_empty() {
+        status!(options, "Truncated {} oversized file(s):", report.truncated.len());
+        for (path, truncation) in &report.truncated {
+            status!(
+                options,
+                "  {} ({} of {} lines omitted)",
+                path.display(),
+                truncation.omitted_lines,
+                truncation.total_lines
+            );
+        }
+    }
 
-        let path = entry.path();
-        
-        if let Some(extension) = path.extension() {
-            if config.allowed_extensions.contains(&extension.to_string_lossy().to_string()) {
-                writeln!(output, "\n=== File: {} ===\n", path.display())?;
-                
-                match fs::read_to_string(path) {
-                    Ok(contents) => {
-                        writeln!(output, "{}", contents)?;
-                    }
-                    Err(e) => {
-                        writeln!(output, "Error reading file: {}", e)?;
-                    }
-                }
-            }
+    if !report.redacted.is_empty() {
+        status!(options, "Redacted {} secret(s):", report.redacted.len());
+        for (path, redaction) in &report.redacted {
+            status!(
+                options,
+                "  {}:{}{} ({})",
+                path.display(),
+                redaction.line,
+                if redaction.in_diff { " of the diff" } else { "" },
+                redaction.kind.name()
+            );
         }
     }
 
-    pb.finish_with_message("Scan complete");
-    
-    println!("Successfully generated code output at: {}", config.output_file.display());
+    if print_stats_after {
+        print_stats(options, &report.tokens, &report.parts)?;
+    }
+
+    if fail_on_secrets && !report.redacted.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} secret(s) found", report.redacted.len()),
+        ));
+    }
     Ok(())
 }
 
-fn is_ignored(entry: &walkdir::DirEntry, ignored_dirs: &[String]) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| ignored_dirs.iter().any(|ignored| s == ignored))
-        .unwrap_or(false)
+fn print_stats(options: &ScanOptions, tokens: &TokenStats, parts: &[PathBuf]) -> io::Result<()> {
+    const LARGEST_FILES: usize = 20;
+
+    status!(options, "\nToken statistics ({}):", options.tokenizer.name());
+    status!(options, "  File contents: {}", tokens.total());
+    // Output streamed to stdout cannot be read back.
+    if !options.writes_to_stdout() {
+        let mut output_tokens = 0;
+        for part in parts {
+            output_tokens += options.tokenizer.count(&fs::read_to_string(part)?);
+        }
+        status!(options, "  Entire output: {}", output_tokens);
+    }
+
+    status!(options, "\nTokens per directory:");
+    for (dir, count) in tokens.directories() {
+        status!(options, "  {:>10}  {}", count, dir.display());
+    }
+
+    let files = tokens.largest_files();
+    status!(options, "\nLargest files:");
+    for (file, count) in files.iter().take(LARGEST_FILES) {
+        status!(options, "  {:>10}  {}", count, file.display());
+    }
+    if files.len() > LARGEST_FILES {
+        status!(options, "  ... and {} more", files.len() - LARGEST_FILES);
+    }
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let mut config_files = Vec::new();
+    let mut output_chosen = from_command_line(&matches, "output");
+    if !cli.no_config {
+        let (settings, sources) =
+            config_file::resolve(&cli.root_path, cli.config.as_deref(), cli.profile.as_deref())?;
+        cli.apply(&matches, &settings)?;
+        config_files = sources;
+        output_chosen |= settings.output.is_some();
+    }
+    // Piped into another command: stream the output instead of writing a file.
+    let splitting = cli.split_tokens.is_some() || cli.split_bytes.is_some();
+    if !output_chosen && !splitting && !io::stdout().is_terminal() {
+        cli.output = PathBuf::from(STDOUT);
+    }
     
-    let config = Config::new(cli);
+    let print_stats_after = cli.stats;
+    let fail_on_secrets = cli.fail_on_secrets;
+    let options = scan_options(cli)?;
     
-    if config.verbose {
-        println!("Analyzing directory: {}", config.root_path.display());
-        println!("Output will be written to: {}", config.output_file.display());
-        println!("Ignored directories: {:?}", config.ignored_dirs);
-        println!("Allowed extensions: {:?}", config.allowed_extensions);
+    if options.verbose {
+        for path in &config_files {
+            status!(options, "Loaded settings from: {}", path.display());
+        }
+        status!(options, "Analyzing directory: {}", options.root_path.display());
+        status!(options, "Output will be written to: {}", options.output_file.display());
+        status!(options, "Ignored directories: {:?}", options.ignored_dirs);
+        status!(options, "Allowed extensions: {:?}", options.allowed_extensions);
+        status!(options, "Include globs: {:?}", options.include_globs());
+        status!(options, "Exclude globs: {:?}", options.exclude_globs());
+        status!(options, "Honoring .gitignore: {}", options.respect_gitignore);
+        status!(options, "Tokenizer: {}", options.tokenizer.name());
+        if options.budget.is_limited() {
+            status!(
+                options,
+                "Budget: {:?} tokens, {:?} bytes, {:?} priority",
+                options.budget.max_tokens, options.budget.max_bytes, options.budget.priority
+            );
+        }
     }
     
-    process_directory(&config)
+    match process_directory(&options, print_stats_after, fail_on_secrets) {
+        // Whoever read the piped output stopped early (e.g. `| head`).
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
 }
\ No newline at end of file
@@ -0,0 +1,363 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::format::{Chunk, FileRecord, Omitted, OutputWriter, Summary, TreeEntry, WriterFactory};
+use crate::tokens::Tokenizer;
+
+/// Output path that streams to standard output instead of a file.
+pub const STDOUT: &str = "-";
+
+/// Maximum size of each output part.
+#[derive(Clone, Copy, Debug)]
+pub enum SplitLimit {
+    Tokens(usize),
+    Bytes(usize),
+}
+
+/// The output file, or the sequence of `name.partN.ext` files when splitting,
+/// or standard output when the path is [`STDOUT`].
+///
+/// Every part starts with the header and tree so it can be read on its own.
+/// Files are never split across parts unless a single file is too large for
+/// an empty part, in which case it is split on line boundaries into chunks.
+/// Each part, trailer included, stays within the split limit unless the
+/// header, tree or a single line is larger than it.
+pub struct Output<'a> {
+    base: PathBuf,
+    new_writer: WriterFactory,
+    summary: Summary<'a>,
+    tree: Option<&'a [TreeEntry]>,
+    omitted: &'a [Omitted<'a>],
+    split: Option<SplitLimit>,
+    tokenizer: &'a Tokenizer,
+    parts: Vec<PathBuf>,
+    file: Option<Box<dyn Write>>,
+    writer: Box<dyn OutputWriter>,
+    prelude_size: usize,
+    /// Room kept at the end of every part for the trailer.
+    trailer_size: usize,
+    part_size: usize,
+    part_has_files: bool,
+}
+
+impl<'a> Output<'a> {
+    pub fn create(
+        base: &Path,
+        new_writer: WriterFactory,
+        summary: Summary<'a>,
+        tree: Option<&'a [TreeEntry]>,
+        omitted: &'a [Omitted<'a>],
+        split: Option<SplitLimit>,
+        tokenizer: &'a Tokenizer,
+    ) -> io::Result<Self> {
+        let mut output = Output {
+            base: base.to_path_buf(),
+            writer: new_writer(),
+            new_writer,
+            summary,
+            tree,
+            omitted,
+            split,
+            tokenizer,
+            parts: Vec::new(),
+            file: None,
+            prelude_size: 0,
+            trailer_size: 0,
+            part_size: 0,
+            part_has_files: false,
+        };
+        // Only the last part lists the omitted files, but which part is last
+        // is not known until the end
+        let mut trailer = Vec::new();
+        let mut writer = (output.new_writer)();
+        writer.write_omitted(&mut trailer, omitted)?;
+        writer.finish(&mut trailer)?;
+        output.trailer_size = output.measure(&trailer);
+        output.start_part()?;
+        Ok(output)
+    }
+
+    pub fn write_file(&mut self, file: &FileRecord) -> io::Result<()> {
+        let Some(limit) = self.limit() else {
+            return self.append(file);
+        };
+
+        if self.part_size + self.measure_file(file)? <= limit {
+            return self.append(file);
+        }
+        if self.part_has_files {
+            self.roll()?;
+            if self.part_size + self.measure_file(file)? <= limit {
+                return self.append(file);
+            }
+        }
+        self.write_chunks(file, limit)
+    }
+
+    /// Writes the trailer and closes the last part, returning the paths written.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        self.end_part(self.omitted)?;
+        Ok(self.parts)
+    }
+
+    /// Room in each part for the header, tree and files.
+    fn limit(&self) -> Option<usize> {
+        self.split.map(|split| match split {
+            SplitLimit::Tokens(limit) | SplitLimit::Bytes(limit) => limit.saturating_sub(self.trailer_size),
+        })
+    }
+
+    fn measure(&self, text: &[u8]) -> usize {
+        match self.split {
+            Some(SplitLimit::Tokens(_)) => self.tokenizer.count(&String::from_utf8_lossy(text)),
+            Some(SplitLimit::Bytes(_)) => text.len(),
+            None => 0,
+        }
+    }
+
+    /// Size of the file's block as the current part would render it, using a
+    /// fresh writer so the state of the current one is untouched.
+    fn measure_file(&self, file: &FileRecord) -> io::Result<usize> {
+        let mut writer = (self.new_writer)();
+        if self.part_has_files {
+            // Files after the first may need a separator
+            let first = FileRecord {
+                path: file.path,
+                size: 0,
+                tokens: 0,
+                contents: None,
+                change: None,
+                renamed_from: None,
+                diff: None,
+                encoding: None,
+                truncation: None,
+                chunk: None,
+            };
+            writer.write_file(&mut io::sink(), &first)?;
+        }
+        let mut block = Vec::new();
+        writer.write_file(&mut block, file)?;
+        Ok(self.measure(&block))
+    }
+
+    fn append(&mut self, file: &FileRecord) -> io::Result<()> {
+        let mut block = Vec::new();
+        self.writer.write_file(&mut block, file)?;
+        self.part_size += self.measure(&block);
+        self.part_has_files = true;
+        self.write_all(&block)
+    }
+
+    fn write_chunks(&mut self, file: &FileRecord, limit: usize) -> io::Result<()> {
+        let Some(Ok(contents)) = &file.contents else {
+            return self.append(file);
+        };
+
+        // A diff goes in a block of its own ahead of the chunks
+        if let Some(diff) = &file.diff {
+            let record = FileRecord {
+                path: file.path,
+                size: diff.len() as u64,
+                tokens: self.tokenizer.count(diff),
+                contents: None,
+                change: file.change,
+                renamed_from: file.renamed_from.clone(),
+                diff: Some(diff.clone()),
+                encoding: None,
+                truncation: None,
+                chunk: None,
+            };
+            if self.part_has_files && self.part_size + self.measure_file(&record)? > limit {
+                self.roll()?;
+            }
+            self.append(&record)?;
+        }
+
+        // Chunks are sized with the widest numbers any of them can have
+        let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+        let widest = Chunk {
+            index: lines.len(),
+            count: lines.len(),
+            first_line: lines.len(),
+            last_line: lines.len(),
+        };
+        let block = |text: &str| FileRecord {
+            path: file.path,
+            size: text.len() as u64,
+            tokens: self.tokenizer.count(text),
+            contents: Some(Ok(text.to_string())),
+            change: file.change,
+            renamed_from: file.renamed_from.clone(),
+            diff: None,
+            encoding: file.encoding.clone(),
+            truncation: file.truncation,
+            chunk: Some(widest),
+        };
+        let overhead = self.measure_file(&block(""))?;
+        let available = limit.saturating_sub(self.prelude_size + overhead);
+        let sizes = lines
+            .iter()
+            .map(|line| Ok(self.measure_file(&block(line))?.saturating_sub(overhead)))
+            .collect::<io::Result<Vec<usize>>>()?;
+
+        let mut chunks: Vec<(usize, String)> = Vec::new();
+        let mut start = 0;
+        while start < lines.len() {
+            let mut end = start + 1;
+            let mut size = sizes[start];
+            while end < lines.len() && size + sizes[end] <= available {
+                size += sizes[end];
+                end += 1;
+            }
+            // Escaping and tokens do not quite add up line by line, so the
+            // block as written gets the final say
+            while end - start > 1 && self.prelude_size + self.measure_file(&block(&lines[start..end].concat()))? > limit {
+                end -= 1;
+            }
+            chunks.push((start + 1, lines[start..end].concat()));
+            start = end;
+        }
+
+        let count = chunks.len();
+        for (index, (first_line, text)) in chunks.into_iter().enumerate() {
+            let chunk = Chunk {
+                index: index + 1,
+                count,
+                first_line,
+                last_line: first_line + text.split_inclusive('\n').count() - 1,
+            };
+            let record = FileRecord {
+                path: file.path,
+                size: text.len() as u64,
+                tokens: self.tokenizer.count(&text),
+                contents: Some(Ok(text)),
+                change: file.change,
+                renamed_from: file.renamed_from.clone(),
+                diff: None,
+                encoding: file.encoding.clone(),
+                truncation: file.truncation,
+                chunk: Some(chunk),
+            };
+            if self.part_has_files && self.part_size + self.measure_file(&record)? > limit {
+                self.roll()?;
+            }
+            self.append(&record)?;
+        }
+        Ok(())
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        self.end_part(&[])?;
+        self.start_part()
+    }
+
+    fn start_part(&mut self) -> io::Result<()> {
+        let number = self.parts.len() + 1;
+        let path = match self.split {
+            Some(_) => part_path(&self.base, number),
+            None => self.base.clone(),
+        };
+
+        let mut summary = self.summary;
+        summary.part = self.split.map(|_| number);
+
+        self.writer = (self.new_writer)();
+        let mut prelude = Vec::new();
+        self.writer.write_header(&mut prelude, &summary)?;
+        if let Some(tree) = self.tree {
+            self.writer.write_tree(&mut prelude, tree)?;
+        }
+        self.writer.begin_contents(&mut prelude)?;
+
+        self.file = Some(if is_stdout(&path) {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(File::create(&path)?)
+        });
+        self.parts.push(path);
+        self.prelude_size = self.measure(&prelude);
+        self.part_size = self.prelude_size;
+        self.part_has_files = false;
+        self.write_all(&prelude)
+    }
+
+    fn end_part(&mut self, omitted: &[Omitted]) -> io::Result<()> {
+        let mut trailer = Vec::new();
+        self.writer.write_omitted(&mut trailer, omitted)?;
+        self.writer.finish(&mut trailer)?;
+        self.write_all(&trailer)?;
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.write_all(bytes),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn is_stdout(path: &Path) -> bool {
+    path.as_os_str() == STDOUT
+}
+
+/// Whether `path` names the output file `base` or one of its parts, ignoring
+/// the directory.
+pub fn is_output_name(path: &Path, base: &Path) -> bool {
+    let (Some(name), Some(base_name)) = (path.file_name(), base.file_name()) else {
+        return false;
+    };
+    if name == base_name {
+        return true;
+    }
+
+    let name = name.to_string_lossy();
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let Some(rest) = name.strip_prefix(&format!("{}.part", stem)) else {
+        return false;
+    };
+    let number = match base.extension() {
+        Some(ext) => rest.strip_suffix(&format!(".{}", ext.to_string_lossy())),
+        None => Some(rest),
+    };
+    number.is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// `code_output.txt` becomes `code_output.part3.txt`.
+fn part_path(base: &Path, number: usize) -> PathBuf {
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match base.extension() {
+        Some(ext) => format!("{}.part{}.{}", stem, number, ext.to_string_lossy()),
+        None => format!("{}.part{}", stem, number),
+    };
+    base.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_parts_after_the_base() {
+        assert_eq!(part_path(Path::new("out/code.txt"), 3), Path::new("out/code.part3.txt"));
+        assert_eq!(part_path(Path::new("dump"), 2), Path::new("dump.part2"));
+    }
+
+    #[test]
+    fn recognizes_output_names() {
+        let base = Path::new("out/code_output.txt");
+        assert!(is_output_name(Path::new("elsewhere/code_output.txt"), base));
+        assert!(is_output_name(Path::new("code_output.part12.txt"), base));
+        assert!(!is_output_name(Path::new("code_output.part.txt"), base));
+        assert!(!is_output_name(Path::new("code_output.partx.txt"), base));
+        assert!(!is_output_name(Path::new("code_output.part1.md"), base));
+        assert!(!is_output_name(Path::new("other.txt"), base));
+        assert!(is_output_name(Path::new("dump.part2"), Path::new("dump")));
+    }
+}
@@ -0,0 +1,959 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicUsize, Ordering},
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    DirEntry, Walk, WalkBuilder,
+};
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use crate::budget::{Budget, Candidate, Priority};
+use crate::content::{self, LoadOptions, Loaded, Oversized, Truncation};
+use crate::format::{self, FileRecord, Omitted, OutputFormat, Summary, TreeEntry, WriterFactory};
+use crate::git::{ChangeKind, ChangeSet, Changes, DiffMode, RepoInfo, Tracked};
+use crate::output::{self, Output, SplitLimit};
+use crate::secrets::Redaction;
+use crate::tokens::{TokenStats, Tokenizer};
+use crate::tree::{self, SortKey, SortOrder, TreeCharset};
+
+/// Directories skipped by default.
+pub const DEFAULT_IGNORED_DIRS: &str = ".git,node_modules,target,.idea,venv,bin,obj,Debug,Release";
+
+/// Extensions whose contents are included by default.
+pub const DEFAULT_EXTENSIONS: &str = "rs,js,py,cpp,c,java,go,ts,cs,csproj,sln,cshtml,razor,json,xml,config,yml,yaml";
+
+/// Everything that decides which files are scanned and how the output is
+/// written. Put together with [`ScanOptions::builder`].
+pub struct ScanOptions {
+    pub root_path: PathBuf,
+    /// Where the output goes; [`STDOUT`](output::STDOUT) streams it to standard output.
+    pub output_file: PathBuf,
+    /// Creates the writer that renders each output part.
+    pub writer: WriterFactory,
+    pub show_tree: bool,
+    pub tree_charset: TreeCharset,
+    /// Describe the git repository in the header, listing this many recent
+    /// commits.
+    pub git_info: Option<usize>,
+    /// Order of the tree and of the files in the contents section.
+    pub sort: SortOrder,
+    /// Include only the contents of files changed in git.
+    pub changes: Option<ChangeSet>,
+    /// With `changes`, show every file in the tree rather than just the
+    /// changed ones.
+    pub full_tree: bool,
+    /// With `changes`, show each file's diff against the base revision.
+    pub diff: Option<DiffMode>,
+    /// Directory and file names skipped wherever they appear.
+    pub ignored_dirs: Vec<String>,
+    /// Extensions of the files whose contents are included, unless include
+    /// globs are given.
+    pub allowed_extensions: Vec<String>,
+    /// Globs selecting the files whose contents are included.
+    include_globs: Vec<String>,
+    include: Option<Gitignore>,
+    /// Globs of files and directories left out of the scan.
+    exclude_globs: Vec<String>,
+    exclude: Gitignore,
+    pub budget: Budget,
+    pub split: Option<SplitLimit>,
+    pub load_options: LoadOptions,
+    /// Honor `.gitignore`, `.ignore` and git's exclude files.
+    pub respect_gitignore: bool,
+    /// When set, only files in the git index are walked.
+    pub tracked: Option<Tracked>,
+    pub tokenizer: Tokenizer,
+    /// Threads used to read and tokenize files; 0 uses one per CPU.
+    pub jobs: usize,
+    /// Print walk errors (unreadable directories, broken links) to stderr.
+    pub verbose: bool,
+}
+
+impl ScanOptions {
+    /// Starts from the defaults of the command-line tool for `root_path`.
+    pub fn builder(root_path: impl Into<PathBuf>) -> ScanOptionsBuilder {
+        ScanOptionsBuilder::new(root_path.into())
+    }
+
+    /// The globs given to [`ScanOptionsBuilder::include`].
+    pub fn include_globs(&self) -> &[String] {
+        &self.include_globs
+    }
+
+    /// The globs given to [`ScanOptionsBuilder::exclude`].
+    pub fn exclude_globs(&self) -> &[String] {
+        &self.exclude_globs
+    }
+
+    pub fn writes_to_stdout(&self) -> bool {
+        output::is_stdout(&self.output_file)
+    }
+
+    /// Whether file blocks carry the files' contents, which `diff` may replace
+    /// with just the diff.
+    pub fn shows_contents(&self) -> bool {
+        self.diff.is_none_or(|diff| diff.full_contents)
+    }
+
+    /// Whether the contents of the file at `path` belong in the output.
+    pub fn is_code_file(&self, path: &Path) -> bool {
+        match &self.include {
+            Some(include) => include.matched_path_or_any_parents(path, false).is_ignore(),
+            None => path
+                .extension()
+                .map(|ext| self.allowed_extensions.contains(&ext.to_string_lossy().to_string()))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Builds [`ScanOptions`], compiling the globs in
+/// [`build`](ScanOptionsBuilder::build).
+pub struct ScanOptionsBuilder {
+    root_path: PathBuf,
+    output_file: PathBuf,
+    writer: WriterFactory,
+    show_tree: bool,
+    tree_charset: TreeCharset,
+    git_info: Option<usize>,
+    sort: SortOrder,
+    changes: Option<ChangeSet>,
+    full_tree: bool,
+    diff: Option<DiffMode>,
+    ignored_dirs: Vec<String>,
+    extensions: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    max_tokens: Option<usize>,
+    max_bytes: Option<u64>,
+    priority: Priority,
+    priority_globs: Vec<String>,
+    split: Option<SplitLimit>,
+    load_options: LoadOptions,
+    respect_gitignore: bool,
+    tracked_only: bool,
+    tokenizer: Option<Tokenizer>,
+    jobs: usize,
+    verbose: bool,
+}
+
+impl ScanOptionsBuilder {
+    fn new(root_path: PathBuf) -> Self {
+        ScanOptionsBuilder {
+            root_path,
+            output_file: PathBuf::from("code_output.txt"),
+            writer: OutputFormat::Text.factory(),
+            show_tree: true,
+            tree_charset: TreeCharset::Unicode,
+            git_info: None,
+            sort: SortOrder::DirsFirst,
+            changes: None,
+            full_tree: false,
+            diff: None,
+            ignored_dirs: split_list(DEFAULT_IGNORED_DIRS),
+            extensions: split_list(DEFAULT_EXTENSIONS),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_tokens: None,
+            max_bytes: None,
+            priority: Priority::Default,
+            priority_globs: Vec::new(),
+            split: None,
+            load_options: LoadOptions {
+                include_binary: None,
+                normalize_newlines: false,
+                line_numbers: None,
+                redact: None,
+                max_file_size: None,
+                oversized: Oversized::Head,
+            },
+            respect_gitignore: true,
+            tracked_only: false,
+            tokenizer: None,
+            jobs: 0,
+            verbose: false,
+        }
+    }
+
+    pub fn output_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.output_file = path.into();
+        self
+    }
+
+    /// Uses one of the built-in formats.
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.writer = format.factory();
+        self
+    }
+
+    /// Uses a custom writer, created afresh for each output part.
+    pub fn writer(mut self, writer: WriterFactory) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    pub fn show_tree(mut self, show_tree: bool) -> Self {
+        self.show_tree = show_tree;
+        self
+    }
+
+    pub fn tree_charset(mut self, charset: TreeCharset) -> Self {
+        self.tree_charset = charset;
+        self
+    }
+
+    /// Adds the commit, branch, dirty state and the last `log` commit
+    /// subjects of the git repository containing the root to the header.
+    pub fn git_info(mut self, log: Option<usize>) -> Self {
+        self.git_info = log;
+        self
+    }
+
+    pub fn sort(mut self, order: SortOrder) -> Self {
+        self.sort = order;
+        self
+    }
+
+    /// Includes only the contents of files changed in the git repository
+    /// containing the root.
+    pub fn changes(mut self, changes: Option<ChangeSet>) -> Self {
+        self.changes = changes;
+        self
+    }
+
+    pub fn full_tree(mut self, full_tree: bool) -> Self {
+        self.full_tree = full_tree;
+        self
+    }
+
+    /// Shows the unified diff of each changed file, with or without its full
+    /// contents. Needs [`changes`](ScanOptionsBuilder::changes).
+    pub fn diff(mut self, diff: Option<DiffMode>) -> Self {
+        self.diff = diff;
+        self
+    }
+
+    pub fn ignored_dirs<S: Into<String>>(mut self, dirs: impl IntoIterator<Item = S>) -> Self {
+        self.ignored_dirs = dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn extensions<S: Into<String>>(mut self, extensions: impl IntoIterator<Item = S>) -> Self {
+        self.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds a gitignore-style glob selecting files to include instead of
+    /// matching on extensions.
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Adds a gitignore-style glob of files and directories to leave out.
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Adds a glob whose files claim the budget before later globs and
+    /// unmatched files.
+    pub fn priority_glob(mut self, glob: impl Into<String>) -> Self {
+        self.priority_globs.push(glob.into());
+        self
+    }
+
+    pub fn split(mut self, split: Option<SplitLimit>) -> Self {
+        self.split = split;
+        self
+    }
+
+    pub fn load_options(mut self, options: LoadOptions) -> Self {
+        self.load_options = options;
+        self
+    }
+
+    pub fn respect_gitignore(mut self, respect: bool) -> Self {
+        self.respect_gitignore = respect;
+        self
+    }
+
+    /// Walks only the files in the index of the git repository containing the
+    /// root, which is read by [`build`](ScanOptionsBuilder::build).
+    pub fn tracked_only(mut self, tracked_only: bool) -> Self {
+        self.tracked_only = tracked_only;
+        self
+    }
+
+    /// Defaults to the heuristic tokenizer.
+    pub fn tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.tokenizer = Some(tokenizer);
+        self
+    }
+
+    /// Number of threads reading files; 0, the default, uses one per CPU.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Compiles the globs and reads the git index if needed, failing on invalid
+    /// glob syntax, when the index cannot be read, when asked to split output
+    /// that goes to stdout or for diffs without a change set.
+    pub fn build(self) -> io::Result<ScanOptions> {
+        if self.diff.is_some() && self.changes.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "diffs need a set of git changes to compare",
+            ));
+        }
+        if self.split.is_some() && output::is_stdout(&self.output_file) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "split output needs an output file, not stdout",
+            ));
+        }
+
+        let root = &self.root_path;
+        let include = if self.include.is_empty() {
+            None
+        } else {
+            Some(build_globs(root, &self.include)?)
+        };
+        let exclude = build_globs(root, &self.exclude)?;
+        let tracked = if self.tracked_only {
+            Some(Tracked::load(root)?)
+        } else {
+            None
+        };
+        let priority_globs = self
+            .priority_globs
+            .iter()
+            .map(|glob| build_globs(root, std::slice::from_ref(glob)))
+            .collect::<io::Result<_>>()?;
+
+        Ok(ScanOptions {
+            output_file: self.output_file,
+            writer: self.writer,
+            show_tree: self.show_tree,
+            tree_charset: self.tree_charset,
+            git_info: self.git_info,
+            sort: self.sort,
+            changes: self.changes,
+            full_tree: self.full_tree,
+            diff: self.diff,
+            ignored_dirs: self.ignored_dirs,
+            allowed_extensions: self.extensions,
+            include_globs: self.include,
+            include,
+            exclude_globs: self.exclude,
+            exclude,
+            budget: Budget {
+                max_tokens: self.max_tokens,
+                max_bytes: self.max_bytes,
+                priority: self.priority,
+                priority_globs,
+            },
+            split: self.split,
+            load_options: self.load_options,
+            respect_gitignore: self.respect_gitignore,
+            tracked,
+            tokenizer: self.tokenizer.unwrap_or(Tokenizer::Heuristic),
+            jobs: self.jobs,
+            verbose: self.verbose,
+            root_path: self.root_path,
+        })
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(|s| s.to_string()).collect()
+}
+
+/// Compiles gitignore-style `globs` into a matcher anchored at `root`.
+fn build_globs(root: &Path, globs: &[String]) -> io::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for glob in globs {
+        builder
+            .add_line(None, glob)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    }
+    builder
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// A regular file found by the walk.
+#[derive(Clone, Debug)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    /// Number of directories between the root and the file.
+    pub depth: usize,
+    /// Size on disk in bytes.
+    pub size: u64,
+    /// Whether the file's contents are included, by extension or `include` glob.
+    pub is_code: bool,
+}
+
+/// Iterator over the files under the root that survive the ignore rules, in
+/// walk order. Created by [`files`].
+pub struct Files<'a> {
+    walk: Walk,
+    options: &'a ScanOptions,
+    own_output: OwnOutput,
+}
+
+impl Iterator for Files<'_> {
+    type Item = FileEntry;
+
+    fn next(&mut self) -> Option<FileEntry> {
+        for entry in self.walk.by_ref() {
+            let Some(entry) = report_errors(entry, self.options.verbose) else {
+                continue;
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let is_code = self.options.is_code_file(entry.path());
+            if self.own_output.contains(entry.path(), is_code) {
+                continue;
+            }
+            return Some(FileEntry {
+                depth: entry.depth(),
+                size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                is_code,
+                path: entry.into_path(),
+            });
+        }
+        None
+    }
+}
+
+/// Lists the files under `options.root_path`.
+pub fn files(options: &ScanOptions) -> Files<'_> {
+    Files {
+        walk: walker(options),
+        options,
+        own_output: OwnOutput::new(options),
+    }
+}
+
+/// Recognizes the files written by this run and, among the code files, dumps
+/// left behind by earlier runs, so that no output ends up inside itself.
+struct OwnOutput {
+    base: Option<PathBuf>,
+    /// Canonical directory the output is written to.
+    dir: Option<PathBuf>,
+}
+
+impl OwnOutput {
+    fn new(options: &ScanOptions) -> Self {
+        if options.writes_to_stdout() {
+            return OwnOutput { base: None, dir: None };
+        }
+        let base = &options.output_file;
+        let parent = match base.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        OwnOutput {
+            base: Some(base.clone()),
+            dir: parent.canonicalize().ok(),
+        }
+    }
+
+    fn contains(&self, path: &Path, is_code: bool) -> bool {
+        if let (Some(base), Some(dir)) = (&self.base, &self.dir) {
+            if output::is_output_name(path, base)
+                && path.parent().and_then(|p| p.canonicalize().ok()).as_ref() == Some(dir)
+            {
+                return true;
+            }
+        }
+        is_code && is_previous_dump(path)
+    }
+}
+
+/// Whether the file starts with the header of a dump written by an earlier run.
+fn is_previous_dump(path: &Path) -> bool {
+    let mut head = Vec::with_capacity(format::MARKER_LEN);
+    File::open(path)
+        .and_then(|file| file.take(format::MARKER_LEN as u64).read_to_end(&mut head))
+        .is_ok_and(|_| format::is_generated(&head))
+}
+
+/// What a call to [`generate`] produced.
+pub struct Report {
+    pub total_files: usize,
+    pub code_files: usize,
+    /// The output files written, one per part.
+    pub parts: Vec<PathBuf>,
+    /// Files that were cut down to `max_file_size`.
+    pub truncated: Vec<(PathBuf, Truncation)>,
+    /// Code files left out (binary, oversized or over budget), with the reason.
+    pub omitted: Vec<(PathBuf, String)>,
+    /// Secrets replaced in the included files and diffs.
+    pub redacted: Vec<(PathBuf, Redaction)>,
+    /// Token counts of the included files.
+    pub tokens: TokenStats,
+}
+
+/// One entry of the walk, directories included.
+struct Node {
+    path: PathBuf,
+    depth: usize,
+    is_dir: bool,
+    is_file: bool,
+    is_code: bool,
+    /// Size on disk in bytes.
+    size: u64,
+    modified: Option<SystemTime>,
+    change: Option<ChangeKind>,
+    renamed_from: Option<PathBuf>,
+    /// Unified diff of a changed code file, in diff mode.
+    diff: Option<String>,
+    /// Secrets replaced in `diff`.
+    diff_redactions: Vec<Redaction>,
+    /// Contents of a code file, loaded once and used by every section.
+    contents: Option<io::Result<Loaded>>,
+    tokens: usize,
+}
+
+impl Node {
+    /// An entry for a file or directory that no longer exists on disk.
+    fn missing(path: PathBuf, depth: usize, is_dir: bool) -> Self {
+        Node {
+            path,
+            depth,
+            is_dir,
+            is_file: false,
+            is_code: false,
+            size: 0,
+            modified: None,
+            change: None,
+            renamed_from: None,
+            diff: None,
+            diff_redactions: Vec::new(),
+            contents: None,
+            tokens: 0,
+        }
+    }
+
+    /// Keeps `patch` as the node's diff, redacted like the file contents.
+    fn set_diff(&mut self, patch: Option<&str>, options: &ScanOptions) {
+        let Some(patch) = patch else {
+            return;
+        };
+        match &options.load_options.redact {
+            Some(redactor) => {
+                let (diff, redactions) = redactor.redact(patch);
+                self.diff = Some(diff);
+                self.diff_redactions = redactions
+                    .into_iter()
+                    .map(|redaction| Redaction { in_diff: true, ..redaction })
+                    .collect();
+            }
+            None => self.diff = Some(patch.to_string()),
+        }
+    }
+}
+
+/// Everything the single walk of the root found. The header counts, the tree
+/// and the contents section are all rendered from it, so they always agree
+/// even if files change while the tool runs.
+struct Model {
+    /// In the order of `options.sort`, each directory followed by its contents.
+    nodes: Vec<Node>,
+    total_files: usize,
+}
+
+/// Walks the root once, sorts the entries, then loads and tokenizes the code files on
+/// `options.jobs` threads, calling `progress` after each file.
+fn build_model(options: &ScanOptions, progress: &(impl Fn(usize, usize) + Sync)) -> io::Result<Model> {
+    let own_output = OwnOutput::new(options);
+    let mut nodes = Vec::new();
+
+    for entry in walker(options).filter_map(|entry| report_errors(entry, options.verbose)) {
+        let file_type = entry.file_type();
+        let is_file = file_type.is_some_and(|t| t.is_file());
+        let is_code = is_file && options.is_code_file(entry.path());
+        if is_file && own_output.contains(entry.path(), is_code) {
+            continue;
+        }
+
+        let metadata = if is_file { entry.metadata().ok() } else { None };
+        nodes.push(Node {
+            depth: entry.depth(),
+            is_dir: file_type.is_some_and(|t| t.is_dir()),
+            is_file,
+            is_code,
+            size: metadata.as_ref().map_or(0, |m| m.len()),
+            modified: metadata.and_then(|m| m.modified().ok()),
+            change: None,
+            renamed_from: None,
+            diff: None,
+            diff_redactions: Vec::new(),
+            // Loaded below, once the walk is done and the total is known
+            contents: None,
+            tokens: 0,
+            path: entry.into_path(),
+        });
+    }
+
+    if let Some(set) = &options.changes {
+        nodes = select_changed(nodes, options, set)?;
+    }
+
+    let mut nodes = tree::sort(nodes, options.sort, |node| SortKey {
+        path: &node.path,
+        depth: node.depth,
+        is_dir: node.is_dir,
+        size: node.size,
+        modified: node.modified,
+    });
+
+    let total_files = nodes.iter().filter(|node| node.is_file).count();
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()
+        .map_err(io::Error::other)?;
+    let done = AtomicUsize::new(0);
+
+    // Each node is filled in place, so the walk order survives the threads.
+    pool.install(|| {
+        nodes.par_iter_mut().filter(|node| node.is_file).for_each(|node| {
+            if node.is_code {
+                let mut loaded = content::load(&node.path, &options.load_options);
+                node.tokens = node.diff.as_deref().map_or(0, |diff| options.tokenizer.count(diff));
+                if let Ok(Loaded::Text { text, .. }) = &mut loaded {
+                    if options.shows_contents() {
+                        node.tokens += options.tokenizer.count(text);
+                    } else {
+                        // Only the diff is written
+                        *text = String::new();
+                    }
+                }
+                node.contents = Some(loaded);
+            }
+            progress(done.fetch_add(1, Ordering::Relaxed) + 1, total_files);
+        });
+    });
+
+    Ok(Model { nodes, total_files })
+}
+
+/// Marks the files changed in git, drops the contents of the rest and adds
+/// entries for deleted files. Unless the full tree is wanted, only changed
+/// files and their directories are kept.
+fn select_changed(mut nodes: Vec<Node>, options: &ScanOptions, set: &ChangeSet) -> io::Result<Vec<Node>> {
+    let changes = Changes::load(&options.root_path, set, options.diff)?;
+    let root = options.root_path.canonicalize()?;
+    // Paths in the output start with the root as given, not its canonical form
+    let display = |path: &Path| match path.strip_prefix(&root) {
+        Ok(relative) => options.root_path.join(relative),
+        Err(_) => path.to_path_buf(),
+    };
+
+    for node in &mut nodes {
+        let relative = node.path.strip_prefix(&options.root_path).unwrap_or(&node.path);
+        let change = changes.get(&root.join(relative));
+        node.change = change.map(|change| change.kind);
+        node.is_code &= change.is_some();
+        if let (Some(change), true) = (change, node.is_code) {
+            node.renamed_from = change.old_path.as_deref().map(display);
+            node.set_diff(change.patch.as_deref(), options);
+        }
+    }
+
+    let mut seen: HashSet<PathBuf> = nodes.iter().map(|node| node.path.clone()).collect();
+    let mut deleted: Vec<_> = changes.deleted().filter(|(path, _)| path.starts_with(&root)).collect();
+    deleted.sort_by_key(|(path, _)| *path);
+    for (path, change) in deleted {
+        let path = display(path);
+        if seen.contains(&path) || is_filtered(&path, options) {
+            continue;
+        }
+        // Directories that went away with the file
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&options.root_path) || !seen.insert(dir.to_path_buf()) {
+                break;
+            }
+            nodes.push(Node::missing(dir.to_path_buf(), depth(dir, options), true));
+        }
+        let mut node = Node::missing(path.clone(), depth(&path, options), false);
+        node.change = Some(ChangeKind::Deleted);
+        node.is_code = options.is_code_file(&path);
+        if node.is_code {
+            node.set_diff(change.patch.as_deref(), options);
+            node.tokens = node.diff.as_deref().map_or(0, |diff| options.tokenizer.count(diff));
+        }
+        seen.insert(path);
+        nodes.push(node);
+    }
+
+    let mut keep = HashSet::new();
+    for node in &nodes {
+        if node.change.is_some() {
+            keep.extend(node.path.ancestors().map(Path::to_path_buf));
+        }
+    }
+
+    if !options.full_tree {
+        nodes.retain(|node| node.depth == 0 || keep.contains(&node.path));
+    }
+    Ok(nodes)
+}
+
+/// Number of directories between the root and `path`.
+fn depth(path: &Path, options: &ScanOptions) -> usize {
+    path.strip_prefix(&options.root_path).map_or(0, |relative| relative.components().count())
+}
+
+/// Whether the walk would have skipped `path` through `ignored_dirs` or the
+/// exclude globs.
+fn is_filtered(path: &Path, options: &ScanOptions) -> bool {
+    let relative = path.strip_prefix(&options.root_path).unwrap_or(path);
+    relative
+        .iter()
+        .any(|name| options.ignored_dirs.iter().any(|ignored| name == ignored.as_str()))
+        || options.exclude.matched_path_or_any_parents(path, false).is_ignore()
+}
+
+/// Scans the root and writes the output, calling `progress` with the number
+/// of files read so far and the total after each file. Files are read in
+/// parallel, so `progress` may be called from several threads.
+pub fn generate(options: &ScanOptions, progress: impl Fn(usize, usize) + Sync) -> io::Result<Report> {
+    let Model { mut nodes, total_files } = build_model(options, &progress)?;
+
+    // Set aside binary and oversized files
+    let mut candidates = Vec::new();
+    let mut skipped = Vec::new();
+    for node in &nodes {
+        let diff_size = node.diff.as_ref().map_or(0, |diff| diff.len() as u64);
+        let (count, size) = match &node.contents {
+            None if node.diff.is_some() => (node.tokens, diff_size),
+            None => continue,
+            Some(Ok(Loaded::Text { text, .. })) if options.shows_contents() => {
+                (node.tokens, diff_size + text.len() as u64)
+            }
+            Some(Ok(Loaded::Text { .. })) => (node.tokens, diff_size),
+            Some(Ok(Loaded::Skipped(reason))) => {
+                skipped.push((node.path.clone(), reason.clone()));
+                continue;
+            }
+            Some(Err(_)) => (0, 0),
+        };
+        candidates.push(Candidate {
+            path: node.path.clone(),
+            size,
+            tokens: count,
+        });
+    }
+    let code_files = candidates.len();
+
+    // Skipped binary and oversized files, then whatever does not fit in the budget
+    let over_budget = options.budget.omitted(&candidates);
+    let omitted: Vec<Omitted> = skipped
+        .iter()
+        .map(|(path, reason)| Omitted {
+            path,
+            reason: reason.clone(),
+        })
+        .chain(over_budget.iter().map(|c| Omitted {
+            path: &c.path,
+            reason: format!("over budget: {} tokens, {} bytes", c.tokens, c.size),
+        }))
+        .collect();
+    let omitted_paths: HashSet<&Path> = omitted.iter().map(|o| o.path).collect();
+
+    // Only the text of the files that are written is needed from here on
+    for node in nodes.iter_mut().filter(|node| omitted_paths.contains(node.path.as_path())) {
+        node.contents = None;
+    }
+
+    // Count the tokens of what is actually written
+    let mut tokens = TokenStats::new(&options.root_path);
+    for candidate in candidates.iter().filter(|c| !omitted_paths.contains(c.path.as_path())) {
+        tokens.add_file(&candidate.path, candidate.tokens);
+    }
+
+    let repository = match options.git_info {
+        Some(log) => Some(RepoInfo::load(&options.root_path, log)?),
+        None => None,
+    };
+    let summary = Summary {
+        root: &options.root_path,
+        total_files,
+        code_files,
+        total_tokens: tokens.total(),
+        tokenizer: options.tokenizer.name(),
+        part: None,
+        repository: repository.as_ref(),
+    };
+
+    // Generate directory tree
+    let tree: Vec<TreeEntry> = if options.show_tree {
+        let entries = nodes
+            .iter()
+            .map(|node| TreeEntry {
+                path: node.path.clone(),
+                depth: node.depth,
+                name: node.path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                is_dir: node.is_dir,
+                tokens: tokens.file(&node.path).or_else(|| tokens.directory(&node.path)),
+                prefix: String::new(),
+                change: node.change,
+            })
+            .collect();
+        tree::arrange(entries, options.tree_charset)
+    } else {
+        Vec::new()
+    };
+
+    // Create output file(s)
+    let mut output = Output::create(
+        &options.output_file,
+        options.writer.clone(),
+        summary,
+        options.show_tree.then_some(tree.as_slice()),
+        &omitted,
+        options.split,
+        &options.tokenizer,
+    )?;
+
+    // Write code files
+    let mut truncated: Vec<(PathBuf, Truncation)> = Vec::new();
+    let mut redacted: Vec<(PathBuf, Redaction)> = Vec::new();
+    for node in nodes {
+        if omitted_paths.contains(node.path.as_path()) {
+            continue;
+        }
+
+        let (contents, encoding, truncation, redactions) = match node.contents {
+            None if node.diff.is_some() => (None, None, None, Vec::new()),
+            None => continue,
+            Some(Ok(Loaded::Text { text, encoding, truncation, redactions })) if options.shows_contents() => {
+                (Some(Ok(text)), encoding, truncation, redactions)
+            }
+            Some(Ok(Loaded::Text { .. })) => (None, None, None, Vec::new()),
+            Some(Ok(Loaded::Skipped(_))) => continue,
+            Some(Err(e)) => (Some(Err(e)), None, None, Vec::new()),
+        };
+        redacted.extend(
+            node.diff_redactions
+                .iter()
+                .chain(&redactions)
+                .map(|redaction| (node.path.clone(), *redaction)),
+        );
+        if let Some(truncation) = truncation {
+            truncated.push((node.path.clone(), truncation));
+        }
+        output.write_file(&FileRecord {
+            path: &node.path,
+            size: node.size,
+            tokens: tokens.file(&node.path).unwrap_or(0),
+            contents,
+            change: node.change,
+            renamed_from: node.renamed_from,
+            diff: node.diff,
+            encoding,
+            truncation,
+            chunk: None,
+        })?;
+    }
+
+    let parts = output.finish()?;
+
+    let omitted = omitted
+        .into_iter()
+        .map(|o| (o.path.to_path_buf(), o.reason))
+        .collect();
+    Ok(Report {
+        total_files,
+        code_files,
+        parts,
+        truncated,
+        omitted,
+        redacted,
+        tokens,
+    })
+}
+
+/// Walks `root_path`, skipping `ignored_dirs` and, unless disabled, anything
+/// excluded by nested `.gitignore`/`.ignore` files, `.git/info/exclude` and the
+/// user's global git excludes file. With `tracked`, untracked paths are
+/// skipped too.
+fn walker(options: &ScanOptions) -> Walk {
+    let ignored_dirs = options.ignored_dirs.clone();
+    let exclude = options.exclude.clone();
+    let tracked = options.tracked.clone();
+    let use_gitignore = options.respect_gitignore;
+
+    WalkBuilder::new(&options.root_path)
+        .hidden(false)
+        .parents(use_gitignore)
+        .ignore(use_gitignore)
+        .git_ignore(use_gitignore)
+        .git_global(use_gitignore)
+        .git_exclude(use_gitignore)
+        .require_git(false)
+        .filter_entry(move |e| {
+            !is_ignored(e, &ignored_dirs) && !is_excluded(e, &exclude) && is_tracked(e, tracked.as_ref())
+        })
+        .build()
+}
+
+fn report_errors(entry: Result<DirEntry, ignore::Error>, verbose: bool) -> Option<DirEntry> {
+    match entry {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            if verbose {
+                eprintln!("Warning: {}", e);
+            }
+            None
+        }
+    }
+}
+
+fn is_ignored(entry: &DirEntry, ignored_dirs: &[String]) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .map(|s| ignored_dirs.iter().any(|ignored| s == ignored))
+        .unwrap_or(false)
+}
+
+fn is_excluded(entry: &DirEntry, exclude: &Gitignore) -> bool {
+    let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+    entry.depth() > 0 && exclude.matched(entry.path(), is_dir).is_ignore()
+}
+
+fn is_tracked(entry: &DirEntry, tracked: Option<&Tracked>) -> bool {
+    let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+    entry.depth() == 0 || tracked.is_none_or(|tracked| tracked.contains(entry.path(), is_dir))
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::ValueEnum;
+use fancy_regex::Regex;
+
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+
+/// How tokens are counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TokenizerKind {
+    /// Fast estimate of roughly four characters per token
+    Heuristic,
+    /// Exact counts with the cl100k_base BPE (requires --tokenizer-file)
+    Cl100k,
+    /// Exact counts with the o200k_base BPE (requires --tokenizer-file)
+    O200k,
+}
+
+impl TokenizerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenizerKind::Heuristic => "heuristic",
+            TokenizerKind::Cl100k => "cl100k",
+            TokenizerKind::O200k => "o200k",
+        }
+    }
+}
+
+pub enum Tokenizer {
+    Heuristic,
+    Bpe(TokenizerKind, Bpe),
+}
+
+impl Tokenizer {
+    /// Builds the tokenizer, reading the `.tiktoken` vocabulary at `vocab_file`
+    /// for the BPE kinds.
+    pub fn load(kind: TokenizerKind, vocab_file: Option<&Path>) -> io::Result<Self> {
+        let pattern = match kind {
+            TokenizerKind::Heuristic => return Ok(Tokenizer::Heuristic),
+            TokenizerKind::Cl100k => CL100K_PATTERN,
+            TokenizerKind::O200k => O200K_PATTERN,
+        };
+        let vocab_file = vocab_file.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--tokenizer {} requires --tokenizer-file", kind.name()),
+            )
+        })?;
+        Ok(Tokenizer::Bpe(kind, Bpe::load(vocab_file, pattern)?))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tokenizer::Heuristic => TokenizerKind::Heuristic.name(),
+            Tokenizer::Bpe(kind, _) => kind.name(),
+        }
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Tokenizer::Heuristic => text.chars().count().div_ceil(4),
+            Tokenizer::Bpe(_, bpe) => bpe.count(text),
+        }
+    }
+}
+
+/// A byte-level BPE in the format used by tiktoken: each vocabulary line is a
+/// base64-encoded token followed by its rank.
+pub struct Bpe {
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: Regex,
+}
+
+impl Bpe {
+    fn load(path: &Path, pattern: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut ranks = HashMap::new();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let (token, rank) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid(format!("{}:{}: expected `<token> <rank>`", path.display(), number + 1)))?;
+            let token = STANDARD
+                .decode(token)
+                .map_err(|e| invalid(format!("{}:{}: {}", path.display(), number + 1, e)))?;
+            let rank = rank
+                .trim()
+                .parse()
+                .map_err(|e| invalid(format!("{}:{}: {}", path.display(), number + 1, e)))?;
+            ranks.insert(token, rank);
+        }
+
+        let pattern = Regex::new(pattern).map_err(|e| invalid(e.to_string()))?;
+        Ok(Bpe { ranks, pattern })
+    }
+
+    fn count(&self, text: &str) -> usize {
+        self.pattern
+            .find_iter(text)
+            .filter_map(Result::ok)
+            .map(|piece| {
+                let piece = piece.as_str().as_bytes();
+                if self.ranks.contains_key(piece) {
+                    1
+                } else {
+                    self.merge_count(piece)
+                }
+            })
+            .sum()
+    }
+
+    /// Repeatedly merges the adjacent pair with the lowest rank, returning the
+    /// number of tokens left once no pair is in the vocabulary.
+    fn merge_count(&self, piece: &[u8]) -> usize {
+        // Start offsets of each part; the final element marks the end of the piece.
+        let mut bounds: Vec<usize> = (0..=piece.len()).collect();
+
+        while bounds.len() > 2 {
+            let best = (0..bounds.len() - 2)
+                .filter_map(|i| {
+                    self.ranks
+                        .get(&piece[bounds[i]..bounds[i + 2]])
+                        .map(|&rank| (rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+
+        bounds.len() - 1
+    }
+}
+
+/// Token counts per file, rolled up into every directory between the file and
+/// the scan root.
+pub struct TokenStats {
+    root: PathBuf,
+    files: HashMap<PathBuf, usize>,
+    directories: HashMap<PathBuf, usize>,
+    total: usize,
+}
+
+impl TokenStats {
+    pub fn new(root: &Path) -> Self {
+        TokenStats {
+            root: root.to_path_buf(),
+            files: HashMap::new(),
+            directories: HashMap::new(),
+            total: 0,
+        }
+    }
+
+    pub fn add_file(&mut self, path: &Path, tokens: usize) {
+        self.files.insert(path.to_path_buf(), tokens);
+        self.total += tokens;
+
+        for dir in path.ancestors().skip(1) {
+            *self.directories.entry(dir.to_path_buf()).or_default() += tokens;
+            if dir == self.root {
+                break;
+            }
+        }
+    }
+
+    pub fn file(&self, path: &Path) -> Option<usize> {
+        self.files.get(path).copied()
+    }
+
+    pub fn directory(&self, path: &Path) -> Option<usize> {
+        self.directories.get(path).copied()
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Files sorted by descending token count.
+    pub fn largest_files(&self) -> Vec<(&Path, usize)> {
+        let mut files: Vec<_> = self.files.iter().map(|(p, &t)| (p.as_path(), t)).collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        files
+    }
+
+    /// Directories sorted by path.
+    pub fn directories(&self) -> Vec<(&Path, usize)> {
+        let mut directories: Vec<_> = self.directories.iter().map(|(p, &t)| (p.as_path(), t)).collect();
+        directories.sort();
+        directories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bpe(tokens: &[&str]) -> Bpe {
+        Bpe {
+            ranks: tokens
+                .iter()
+                .enumerate()
+                .map(|(rank, token)| (token.as_bytes().to_vec(), rank as u32))
+                .collect(),
+            pattern: Regex::new(CL100K_PATTERN).unwrap(),
+        }
+    }
+
+    #[test]
+    fn merges_lowest_ranked_pairs_first() {
+        let bpe = bpe(&["ab", "cd", "abcd"]);
+        assert_eq!(bpe.merge_count(b"abcd"), 1);
+        assert_eq!(bpe.merge_count(b"abce"), 3);
+        assert_eq!(bpe.merge_count(b"xyz"), 3);
+    }
+
+    #[test]
+    fn counts_whole_pieces_in_the_vocabulary_as_one() {
+        let bpe = bpe(&["hello", " world"]);
+        assert_eq!(bpe.count("hello world"), 2);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use clap::ValueEnum;
+
+use crate::format::TreeEntry;
+
+/// Characters used to draw the directory tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TreeCharset {
+    /// Box-drawing characters, as printed by tree(1)
+    Unicode,
+    /// Plain ASCII for terminals and tools that mangle box-drawing characters
+    Ascii,
+}
+
+impl TreeCharset {
+    fn connector(self, is_last: bool) -> &'static str {
+        match (self, is_last) {
+            (TreeCharset::Unicode, false) => "├── ",
+            (TreeCharset::Unicode, true) => "└── ",
+            (TreeCharset::Ascii, false) => "|-- ",
+            (TreeCharset::Ascii, true) => "`-- ",
+        }
+    }
+
+    fn guide(self, is_last: bool) -> &'static str {
+        match (self, is_last) {
+            (_, true) => "    ",
+            (TreeCharset::Unicode, false) => "│   ",
+            (TreeCharset::Ascii, false) => "|   ",
+        }
+    }
+}
+
+/// Order of the entries within each directory, used for both the tree and the
+/// contents section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Directories before files, then by case-insensitive name, like tree(1)
+    DirsFirst,
+    /// By name, byte for byte, so files come out in plain path order
+    Path,
+    /// Largest first; a directory weighs as much as the files below it
+    Size,
+    /// Most recently modified first; a directory counts as its newest file
+    Mtime,
+}
+
+/// What `sort` needs to know about an entry.
+pub struct SortKey<'a> {
+    pub path: &'a Path,
+    pub depth: usize,
+    pub is_dir: bool,
+    /// Size of a file in bytes; ignored for directories.
+    pub size: u64,
+    /// Modification time of a file; ignored for directories.
+    pub modified: Option<SystemTime>,
+}
+
+/// Puts walked entries in depth-first order, each directory followed by its
+/// children sorted by `order`. Ties are broken by name, so the result does not
+/// depend on the order the filesystem returned the entries in.
+pub fn sort<T>(entries: Vec<T>, order: SortOrder, key: impl Fn(&T) -> SortKey) -> Vec<T> {
+    struct Item<T> {
+        entry: T,
+        path: PathBuf,
+        name: String,
+        is_dir: bool,
+        size: u64,
+        modified: Option<SystemTime>,
+    }
+
+    let mut root = None;
+    let mut children: HashMap<PathBuf, Vec<Item<T>>> = HashMap::new();
+    for entry in entries {
+        let key = key(&entry);
+        let is_root = key.depth == 0;
+        let parent = key.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let item = Item {
+            path: key.path.to_path_buf(),
+            name: key.path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            is_dir: key.is_dir,
+            size: if key.is_dir { 0 } else { key.size },
+            modified: if key.is_dir { None } else { key.modified },
+            entry,
+        };
+        if is_root {
+            root = Some(item);
+        } else {
+            children.entry(parent).or_default().push(item);
+        }
+    }
+    let Some(root) = root else {
+        return Vec::new();
+    };
+
+    // Directories weigh as much as their contents, computed bottom-up.
+    fn aggregate<T>(dir: &Path, children: &mut HashMap<PathBuf, Vec<Item<T>>>) -> (u64, Option<SystemTime>) {
+        let Some(mut items) = children.remove(dir) else {
+            return (0, None);
+        };
+        let mut total = (0, None);
+        for item in &mut items {
+            if item.is_dir {
+                (item.size, item.modified) = aggregate(&item.path.clone(), children);
+            }
+            total.0 += item.size;
+            total.1 = total.1.max(item.modified);
+        }
+        children.insert(dir.to_path_buf(), items);
+        total
+    }
+    if matches!(order, SortOrder::Size | SortOrder::Mtime) {
+        aggregate(&root.path, &mut children);
+    }
+
+    fn push<T>(item: Item<T>, order: SortOrder, children: &mut HashMap<PathBuf, Vec<Item<T>>>, sorted: &mut Vec<T>) {
+        let mut items = children.remove(&item.path).unwrap_or_default();
+        sorted.push(item.entry);
+
+        items.sort_by(|a, b| {
+            let primary = match order {
+                SortOrder::DirsFirst => b
+                    .is_dir
+                    .cmp(&a.is_dir)
+                    .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())),
+                SortOrder::Path => Ordering::Equal,
+                SortOrder::Size => b.size.cmp(&a.size),
+                SortOrder::Mtime => b.modified.cmp(&a.modified),
+            };
+            primary.then_with(|| a.name.cmp(&b.name))
+        });
+        for child in items {
+            push(child, order, children, sorted);
+        }
+    }
+
+    let mut sorted = Vec::new();
+    push(root, order, &mut children, &mut sorted);
+    sorted
+}
+
+/// Fills in each entry's connector prefix, keeping the order of siblings as
+/// given (see `sort`).
+///
+/// The root entry (depth 0) comes first and is labelled with its path.
+pub fn arrange(entries: Vec<TreeEntry>, charset: TreeCharset) -> Vec<TreeEntry> {
+    let mut root = None;
+    let mut children: HashMap<PathBuf, Vec<TreeEntry>> = HashMap::new();
+    for entry in entries {
+        if entry.depth == 0 {
+            root = Some(entry);
+        } else {
+            let parent = entry.path.parent().unwrap_or(Path::new("")).to_path_buf();
+            children.entry(parent).or_default().push(entry);
+        }
+    }
+
+    let Some(mut root) = root else {
+        return Vec::new();
+    };
+    root.name = root.path.display().to_string();
+    root.prefix.clear();
+
+    let root_path = root.path.clone();
+    let mut arranged = vec![root];
+    push_children(&root_path, "", &mut children, charset, &mut arranged);
+    arranged
+}
+
+fn push_children(
+    dir: &Path,
+    guides: &str,
+    children: &mut HashMap<PathBuf, Vec<TreeEntry>>,
+    charset: TreeCharset,
+    arranged: &mut Vec<TreeEntry>,
+) {
+    let Some(entries) = children.remove(dir) else {
+        return;
+    };
+
+    let last = entries.len() - 1;
+    for (index, mut entry) in entries.into_iter().enumerate() {
+        let is_last = index == last;
+        entry.prefix = format!("{}{}", guides, charset.connector(is_last));
+
+        let path = entry.path.clone();
+        let is_dir = entry.is_dir;
+        arranged.push(entry);
+
+        if is_dir {
+            let guides = format!("{}{}", guides, charset.guide(is_last));
+            push_children(&path, &guides, children, charset, arranged);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path, whether it is a directory, and size.
+    type Entry = (&'static str, bool, u64);
+
+    fn sorted(entries: &[Entry], order: SortOrder) -> Vec<&'static str> {
+        sort(entries.to_vec(), order, |(path, is_dir, size)| SortKey {
+            path: Path::new(path),
+            depth: Path::new(path).components().count() - 1,
+            is_dir: *is_dir,
+            size: *size,
+            modified: None,
+        })
+        .into_iter()
+        .map(|(path, _, _)| path)
+        .collect()
+    }
+
+    const ENTRIES: &[Entry] = &[
+        ("r/b.rs", false, 10),
+        ("r/a/z.rs", false, 5),
+        ("r", true, 0),
+        ("r/C.rs", false, 1),
+        ("r/a", true, 0),
+    ];
+
+    #[test]
+    fn sorts_dirs_first_by_case_insensitive_name() {
+        assert_eq!(sorted(ENTRIES, SortOrder::DirsFirst), ["r", "r/a", "r/a/z.rs", "r/b.rs", "r/C.rs"]);
+    }
+
+    #[test]
+    fn sorts_by_path_bytes() {
+        assert_eq!(sorted(ENTRIES, SortOrder::Path), ["r", "r/C.rs", "r/a", "r/a/z.rs", "r/b.rs"]);
+    }
+
+    #[test]
+    fn sorts_directories_by_total_size() {
+        assert_eq!(sorted(ENTRIES, SortOrder::Size), ["r", "r/b.rs", "r/a", "r/a/z.rs", "r/C.rs"]);
+    }
+
+    #[test]
+    fn sort_does_not_depend_on_input_order() {
+        let mut reversed = ENTRIES.to_vec();
+        reversed.reverse();
+        for order in [SortOrder::DirsFirst, SortOrder::Path, SortOrder::Size, SortOrder::Mtime] {
+            assert_eq!(sorted(&reversed, order), sorted(ENTRIES, order));
+        }
+    }
+
+    fn entry(path: &str, depth: usize, is_dir: bool) -> TreeEntry {
+        TreeEntry {
+            path: PathBuf::from(path),
+            depth,
+            name: Path::new(path).file_name().unwrap().to_string_lossy().into_owned(),
+            is_dir,
+            tokens: None,
+            prefix: String::new(),
+            change: None,
+        }
+    }
+
+    fn lines(charset: TreeCharset) -> Vec<String> {
+        let entries = vec![
+            entry("r", 0, true),
+            entry("r/a", 1, true),
+            entry("r/a/z.rs", 2, false),
+            entry("r/b.rs", 1, false),
+        ];
+        arrange(entries, charset).iter().map(TreeEntry::line).collect()
+    }
+
+    #[test]
+    fn arranges_unicode_tree() {
+        assert_eq!(lines(TreeCharset::Unicode), ["r", "├── a/", "│   └── z.rs", "└── b.rs"]);
+    }
+
+    #[test]
+    fn arranges_ascii_tree() {
+        assert_eq!(lines(TreeCharset::Ascii), ["r", "|-- a/", "|   `-- z.rs", "`-- b.rs"]);
+    }
+
+    #[test]
+    fn arranges_nothing_without_a_root() {
+        assert!(arrange(vec![entry("r/b.rs", 1, false)], TreeCharset::Unicode).is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    io::Read,
+    process::{Command, Output, Stdio},
+};
+
+/// A project in a fresh temp directory with a `code_tree.toml`, removed
+/// when dropped.
+struct Project(PathBuf);
+
+impl Project {
+    fn new(name: &str, config: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("code_tree-cli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.join("code_tree.toml"), config).unwrap();
+        Project(dir)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+
+    /// The binary run from inside the project, with stdout piped.
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_code_tree"));
+        command.current_dir(&self.0).arg(&self.0).args(args);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = self.command(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        output
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn command_line_overrides_profile_and_project_config() {
+    let project = Project::new(
+        "config",
+        "output = \"context.md\"\nformat = \"markdown\"\n[profile.plain]\nformat = \"text\"\n",
+    );
+
+    project.run(&[]);
+    assert!(read(&project.path("context.md")).starts_with("# Directory Tree and Code Contents\n"));
+
+    project.run(&["--profile", "plain"]);
+    assert!(read(&project.path("context.md")).starts_with("Directory Tree and Code Contents\n"));
+
+    project.run(&["--profile", "plain", "-f", "xml"]);
+    assert!(read(&project.path("context.md")).starts_with("<code_tree root="));
+
+    let out = project.path("out.txt");
+    project.run(&["--no-config", "-o", out.to_str().unwrap()]);
+    assert!(read(&out).starts_with("Directory Tree and Code Contents\n"));
+}
+
+#[test]
+fn streams_to_stdout_when_piped() {
+    let project = Project::new("pipe", "");
+
+    let piped = project.run(&["--verbose"]);
+    let stdout = String::from_utf8(piped.stdout).unwrap();
+    assert!(stdout.starts_with("Directory Tree and Code Contents\n"));
+    assert!(stdout.contains("fn main() {}"));
+    assert!(!stdout.contains("Analyzing directory"));
+    assert!(String::from_utf8_lossy(&piped.stderr).contains("Analyzing directory"));
+    assert!(!project.path("code_output.txt").exists());
+
+    let jsonl = project.run(&["-o", "-", "-f", "jsonl"]);
+    let records: Vec<serde_json::Value> = String::from_utf8(jsonl.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert!(records[0]["path"].as_str().unwrap().ends_with("main.rs"));
+}
+
+#[test]
+fn exits_quietly_when_the_reader_goes_away() {
+    let project = Project::new("closed", "");
+    let big: String = (0..50_000).map(|i| format!("const C{}: u32 = {};\n", i, i)).collect();
+    fs::write(project.path("src/big.rs"), big).unwrap();
+
+    let mut child = project
+        .command(&["-o", "-"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut head = [0; 64];
+    child.stdout.take().unwrap().read_exact(&mut head).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
@@ -0,0 +1,163 @@
+use std::{fs, path::PathBuf};
+
+use code_tree::{OutputFormat, ScanOptions};
+
+/// A fresh directory under the system temp dir, removed when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("code_tree-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("project/src")).unwrap();
+        fs::write(dir.join("project/src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.join("project/src/lib.rs"), "pub fn lib() {}\n").unwrap();
+        fs::write(dir.join("project/notes.txt"), "not code\n").unwrap();
+        fs::write(dir.join("project/logo.rs"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        Scratch(dir)
+    }
+
+    fn root(&self) -> PathBuf {
+        self.0.join("project")
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn generates_markdown_with_tree_and_contents() {
+    let scratch = Scratch::new("markdown");
+    let output = scratch.0.join("context.md");
+    let options = ScanOptions::builder(scratch.root())
+        .format(OutputFormat::Markdown)
+        .output_file(&output)
+        .build()
+        .unwrap();
+
+    let report = code_tree::generate(&options, |_, _| {}).unwrap();
+    assert_eq!(report.total_files, 4);
+    assert_eq!(report.code_files, 2);
+    assert_eq!(report.parts, vec![output.clone()]);
+    assert_eq!(report.omitted.len(), 1);
+    assert!(report.omitted[0].1.starts_with("binary"));
+
+    let text = fs::read_to_string(&output).unwrap();
+    assert!(text.starts_with("# Directory Tree and Code Contents\n"));
+    assert!(text.contains("└── notes.txt"));
+    assert!(text.contains("```rust\nfn main() {}\n```"));
+    assert!(!text.contains("not code"));
+}
+
+#[test]
+fn excluded_files_are_left_out_of_everything() {
+    let scratch = Scratch::new("exclude");
+    let output = scratch.0.join("out.txt");
+    let options = ScanOptions::builder(scratch.root())
+        .output_file(&output)
+        .exclude("lib.rs")
+        .build()
+        .unwrap();
+
+    let files: Vec<_> = code_tree::files(&options).collect();
+    assert_eq!(files.len(), 3);
+    assert!(files.iter().all(|file| !file.path.ends_with("lib.rs")));
+
+    code_tree::generate(&options, |_, _| {}).unwrap();
+    assert!(!fs::read_to_string(&output).unwrap().contains("lib"));
+}
+
+#[test]
+fn earlier_dumps_are_skipped_by_the_walk() {
+    let scratch = Scratch::new("dumps");
+    let dump = scratch.root().join("old_dump.json");
+    let options = ScanOptions::builder(scratch.root())
+        .format(OutputFormat::Json)
+        .output_file(&dump)
+        .build()
+        .unwrap();
+    code_tree::generate(&options, |_, _| {}).unwrap();
+    fs::write(scratch.root().join("notes.py"), "Directory Tree and Code Contents\n\nprint('hi')\n").unwrap();
+
+    let output = scratch.0.join("out.txt");
+    let options = ScanOptions::builder(scratch.root()).output_file(&output).build().unwrap();
+    let report = code_tree::generate(&options, |_, _| {}).unwrap();
+    assert_eq!(report.total_files, 5);
+    assert!(report.omitted.iter().all(|(path, _)| path != &dump));
+
+    let text = fs::read_to_string(&output).unwrap();
+    assert!(!text.contains("old_dump.json"));
+    assert!(text.contains("print('hi')"));
+}
+
+#[test]
+fn output_does_not_depend_on_the_number_of_jobs() {
+    let scratch = Scratch::new("jobs");
+    for i in 0..40 {
+        let dir = scratch.root().join(format!("mod{}", i % 7));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("file{}.rs", i)), format!("pub const N: usize = {};\n", i).repeat(i + 1)).unwrap();
+    }
+
+    let outputs: Vec<Vec<u8>> = [1, 8]
+        .into_iter()
+        .map(|jobs| {
+            let output = scratch.0.join(format!("out{}.txt", jobs));
+            let options = ScanOptions::builder(scratch.root())
+                .output_file(&output)
+                .jobs(jobs)
+                .build()
+                .unwrap();
+            code_tree::generate(&options, |_, _| {}).unwrap();
+            fs::read(&output).unwrap()
+        })
+        .collect();
+    assert_eq!(outputs[0], outputs[1]);
+}
+
+#[test]
+fn every_part_fits_the_split_limit() {
+    let scratch = Scratch::new("split");
+    let lines: String = (0..300)
+        .map(|i| format!("let s{} = \"<a href=\\\"x\\\">]]> tab\there\";\n", i))
+        .collect();
+    fs::write(scratch.root().join("src/big.rs"), lines).unwrap();
+
+    let formats = [
+        (OutputFormat::Text, "txt"),
+        (OutputFormat::Markdown, "md"),
+        (OutputFormat::Json, "json"),
+        (OutputFormat::Jsonl, "jsonl"),
+        (OutputFormat::Xml, "xml"),
+    ];
+    for (format, extension) in formats {
+        let output = scratch.0.join(format!("out.{}", extension));
+        let options = ScanOptions::builder(scratch.root())
+            .format(format)
+            .output_file(&output)
+            .split(Some(code_tree::output::SplitLimit::Bytes(2000)))
+            .build()
+            .unwrap();
+
+        let report = code_tree::generate(&options, |_, _| {}).unwrap();
+        assert!(report.parts.len() > 1);
+        for part in &report.parts {
+            let size = fs::metadata(part).unwrap().len();
+            assert!(size <= 2000, "{} is {} bytes", part.display(), size);
+        }
+    }
+}
+
+#[test]
+fn splitting_to_stdout_is_rejected() {
+    let error = ScanOptions::builder(".")
+        .output_file("-")
+        .split(Some(code_tree::output::SplitLimit::Bytes(1000)))
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,161 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use code_tree::git::{ChangeSet, DiffMode};
+use code_tree::{OutputFormat, ScanOptions};
+use git2::{IndexAddOption, Repository, Signature};
+use serde_json::Value;
+
+/// A git repository in a fresh temp directory, committed with `src/a.rs`,
+/// `src/b.rs` and `src/gone.rs`, and removed when dropped.
+struct Repo {
+    dir: PathBuf,
+    repo: Repository,
+}
+
+impl Repo {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("code_tree-git-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("project/src")).unwrap();
+        let repo = Repository::init(dir.join("project")).unwrap();
+        let repo = Repo { dir, repo };
+        repo.write("src/a.rs", "fn a() {}\n");
+        repo.write("src/b.rs", "fn b() {}\n");
+        repo.write("src/gone.rs", "fn gone() {}\n");
+        repo.commit("Initial commit");
+        repo
+    }
+
+    fn root(&self) -> PathBuf {
+        self.dir.join("project")
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        fs::write(self.root().join(path), contents).unwrap();
+    }
+
+    /// Stages everything in the working tree, deletions included, and commits it.
+    fn commit(&self, message: &str) {
+        let mut index = self.repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Tester", "tester@example.com").unwrap();
+        let parent = self.repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap();
+    }
+
+    /// Generates JSON output with `configure` applied to the builder.
+    fn generate(&self, configure: impl FnOnce(code_tree::ScanOptionsBuilder) -> code_tree::ScanOptionsBuilder) -> Value {
+        let output = self.dir.join("out.json");
+        let builder = ScanOptions::builder(self.root()).format(OutputFormat::Json).output_file(&output);
+        code_tree::generate(&configure(builder).build().unwrap(), |_, _| {}).unwrap();
+        serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap()
+    }
+}
+
+impl Drop for Repo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// `(path, change)` of each entry of `list`, with paths relative to `root`.
+fn changes(list: &Value, root: &Path) -> Vec<(String, Option<String>)> {
+    list.as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            let path = Path::new(entry["path"].as_str().unwrap());
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            (relative.to_string_lossy().into_owned(), entry["change"].as_str().map(str::to_string))
+        })
+        .collect()
+}
+
+#[test]
+fn changed_since_includes_only_changed_files() {
+    let repo = Repo::new("since");
+    repo.write("src/a.rs", "fn a() { changed() }\n");
+    fs::remove_file(repo.root().join("src/gone.rs")).unwrap();
+
+    let json = repo.generate(|builder| builder.changes(Some(ChangeSet::Since("HEAD".to_string()))));
+    assert_eq!(changes(&json["files"], &repo.root()), [("src/a.rs".to_string(), Some("modified".to_string()))]);
+    assert_eq!(json["files"][0]["contents"], "fn a() { changed() }\n");
+
+    let tree = changes(&json["tree"], &repo.root());
+    assert!(tree.contains(&("src/gone.rs".to_string(), Some("deleted".to_string()))));
+    assert!(!tree.iter().any(|(path, _)| path == "src/b.rs"));
+}
+
+#[test]
+fn diff_mode_embeds_unified_diffs() {
+    let repo = Repo::new("diff");
+    repo.write("src/a.rs", "fn a() { changed() }\n");
+    fs::remove_file(repo.root().join("src/gone.rs")).unwrap();
+
+    let json = repo.generate(|builder| {
+        builder
+            .changes(Some(ChangeSet::Since("HEAD".to_string())))
+            .diff(Some(DiffMode { context_lines: 3, full_contents: false }))
+    });
+    let files = changes(&json["files"], &repo.root());
+    assert_eq!(
+        files,
+        [
+            ("src/a.rs".to_string(), Some("modified".to_string())),
+            ("src/gone.rs".to_string(), Some("deleted".to_string())),
+        ]
+    );
+    let diff = json["files"][0]["diff"].as_str().unwrap();
+    assert!(diff.contains("-fn a() {}\n+fn a() { changed() }\n"));
+    assert!(json["files"][0]["contents"].is_null());
+    assert!(json["files"][1]["diff"].as_str().unwrap().contains("-fn gone() {}"));
+}
+
+#[test]
+fn tracked_only_skips_untracked_files() {
+    let repo = Repo::new("tracked");
+    repo.write("src/new.rs", "fn new() {}\n");
+
+    let options = ScanOptions::builder(repo.root()).tracked_only(true).build().unwrap();
+    let mut files: Vec<PathBuf> = code_tree::files(&options)
+        .map(|file| file.path.strip_prefix(repo.root()).unwrap().to_path_buf())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["src/a.rs", "src/b.rs", "src/gone.rs"].map(PathBuf::from));
+}
+
+#[test]
+fn git_info_describes_the_repository() {
+    let repo = Repo::new("info");
+    repo.write("src/b.rs", "fn b() { two() }\n");
+    repo.commit("Second commit");
+
+    let clean = repo.generate(|builder| builder.git_info(Some(5)));
+    let info = &clean["repository"];
+    let head = repo.repo.head().unwrap();
+    assert_eq!(info["name"], "project");
+    assert_eq!(info["commit"], head.peel_to_commit().unwrap().id().to_string());
+    assert_eq!(info["branch"], head.shorthand().unwrap());
+    assert_eq!(info["dirty"], false);
+    let subjects: Vec<&str> = info["recent_commits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|commit| commit["subject"].as_str().unwrap())
+        .collect();
+    assert_eq!(subjects, ["Second commit", "Initial commit"]);
+
+    repo.write("src/a.rs", "fn a() { edited() }\n");
+    let dirty = repo.generate(|builder| builder.git_info(Some(1)));
+    assert_eq!(dirty["repository"]["dirty"], true);
+    assert_eq!(dirty["repository"]["recent_commits"].as_array().unwrap().len(), 1);
+}